@@ -1,4 +1,277 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Top-level sensor configuration (the contents of `config.yaml`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SensorConfig {
+    pub measurements: Vec<MeasurementConfig>,
+    
+    /// Address for the HTTP server (/metrics, /matches)
+    #[serde(default = "default_listen_addr")]
+    pub listen_addr: String,
+    
+    /// Buffered signatures per subscriber before slow receivers start lagging
+    #[serde(default = "default_signature_channel_capacity")]
+    pub signature_channel_capacity: usize,
+    
+    /// Per-deployment xxh3 seed shared by detector and matcher, so unrelated
+    /// deployments on the same network can't mis-correlate each other's hashes
+    #[serde(default)]
+    pub hash_seed: u64,
+    
+    /// Route this pod's signatures to the matcher through a bounded queue of
+    /// this depth, applying backpressure to detectors instead of dropping
+    #[serde(default)]
+    pub local_signature_queue: Option<usize>,
+    
+    /// Measurements streaming from Pixie at once (each holds a connection);
+    /// the rest queue until a slot frees. Unset = all at once.
+    #[serde(default)]
+    pub max_concurrent_measurements: Option<usize>,
+    
+    /// Label cardinality controls for exported metrics
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    
+    /// Window and refresh of the matcher's Pixie query
+    #[serde(default)]
+    pub matcher_query: MatcherQueryConfig,
+    
+    /// Add both sides' hex fingerprints to match records (WebSocket feed and
+    /// match log lines). Verbose; for diagnosing unexpected (non-)matches.
+    #[serde(default)]
+    pub debug_fingerprints: bool,
+    
+    /// Optional StatsD/DogStatsD sink alongside Prometheus
+    #[serde(default)]
+    pub statsd: Option<StatsdConfig>,
+    
+    /// Replay per-interval latencies through a simulated jitter buffer
+    #[serde(default)]
+    pub jitter_buffer: Option<JitterBufferConfig>,
+    
+    /// Write every match to a Parquet file for warehouse ingestion
+    #[serde(default)]
+    pub parquet_export: Option<ParquetExportConfig>,
+    
+    /// Append every signature this pod detects to a local file, for
+    /// matching across pods offline (`--match-logs`)
+    #[serde(default)]
+    pub signature_log: Option<SignatureLogConfig>,
+    
+    /// Traffic filter for `--replay <capture.pcap>` offline analysis
+    #[serde(default)]
+    pub pcap_replay: PcapReplayConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PcapReplayConfig {
+    /// Keep packets to/from these ports (empty = all)
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    
+    #[serde(default)]
+    pub protocol: ReplayProtocol,
+    
+    /// Pace packets by their capture timestamps at this multiple of real time
+    /// (1.0 = as captured, 10.0 = ten times faster); unset replays flat out
+    #[serde(default)]
+    pub speed: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatcherQueryConfig {
+    /// How far back each query reaches (PxL start_time)
+    #[serde(default = "default_query_window_secs")]
+    pub window_secs: u64,
+    
+    /// Re-issue the query this often; must not exceed window_secs so each
+    /// window overlaps the last. Unset keeps one long-lived stream.
+    #[serde(default)]
+    pub refresh_secs: Option<u64>,
+    
+    /// Pixie table holding the traffic (differs across schema versions)
+    #[serde(default = "default_query_table")]
+    pub table: String,
+    
+    /// Relayed audio ports (local or remote side of the connection)
+    #[serde(default = "default_query_ports")]
+    pub ports: Vec<u16>,
+}
+
+impl Default for MatcherQueryConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: default_query_window_secs(),
+            refresh_secs: None,
+            table: default_query_table(),
+            ports: default_query_ports(),
+        }
+    }
+}
+
+fn default_query_window_secs() -> u64 {
+    10
+}
+
+fn default_query_table() -> String {
+    "socket_data".to_string()
+}
+
+fn default_query_ports() -> Vec<u16> {
+    vec![15000]
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub enum ReplayProtocol {
+    Tcp,
+    Udp,
+    #[default]
+    Any,
+}
+
+/// Which label dimensions reach Prometheus/StatsD. Raw values (e.g. every
+/// interval_id) stay available in logs and the live match feed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// Only these labels are exported (None = all)
+    #[serde(default)]
+    pub allow_labels: Option<Vec<String>>,
+    
+    /// Labels never exported, e.g. ["interval_id"]
+    #[serde(default)]
+    pub deny_labels: Vec<String>,
+    
+    /// Labels exported as a bounded hash bucket instead of the raw value
+    #[serde(default)]
+    pub hash_labels: Vec<String>,
+    
+    #[serde(default = "default_hash_buckets")]
+    pub hash_buckets: u64,
+    
+    /// Serve /metrics as OpenMetrics, with the raw interval_id of the latest
+    /// match attached as an exemplar to each latency histogram bucket
+    #[serde(default)]
+    pub exemplars: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            allow_labels: None,
+            deny_labels: Vec::new(),
+            hash_labels: Vec::new(),
+            hash_buckets: default_hash_buckets(),
+            exemplars: false,
+        }
+    }
+}
+
+fn default_hash_buckets() -> u64 {
+    64
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ParquetExportConfig {
+    pub path: String,
+    
+    /// Matches buffered per row group
+    #[serde(default = "default_row_group_size")]
+    pub row_group_size: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SignatureLogConfig {
+    /// JSON-lines file, appended to across restarts
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatsdConfig {
+    /// Agent address, e.g. "127.0.0.1:8125"
+    pub host: String,
+    
+    /// Prefix prepended to every metric name
+    #[serde(default = "default_statsd_prefix")]
+    pub prefix: String,
+    
+    /// Emit DogStatsD-style `|#key:value` tags (agent must support them)
+    #[serde(default)]
+    pub tags: bool,
+    
+    /// Upper bound on a single datagram; keep below the path MTU
+    #[serde(default = "default_statsd_max_datagram")]
+    pub max_datagram_bytes: usize,
+    
+    /// How often buffered lines are flushed even if the datagram isn't full
+    #[serde(default = "default_statsd_flush_ms")]
+    pub flush_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JitterBufferConfig {
+    /// Audio carried per packet/frame in ms
+    #[serde(default = "default_jitter_frame_ms")]
+    pub frame_ms: u32,
+    
+    /// Buffering added on top of the first frame's latency before playout
+    pub initial_depth_ms: u32,
+    
+    /// Hard cap on buffered audio; frames beyond it are dropped (overrun)
+    pub max_depth_ms: u32,
+    
+    /// Grow on underrun and drain when calm, like a WebRTC NetEQ-style buffer
+    #[serde(default = "default_true")]
+    pub adaptive: bool,
+    
+    /// On-time frames required before the adaptive buffer shrinks by one frame
+    #[serde(default = "default_jitter_shrink_after")]
+    pub shrink_after_frames: u32,
+    
+    /// Latencies kept per interval before a report is produced
+    #[serde(default = "default_jitter_window")]
+    pub window: usize,
+}
+
+fn default_listen_addr() -> String {
+    "0.0.0.0:9090".to_string()
+}
+
+fn default_signature_channel_capacity() -> usize {
+    1000
+}
+
+fn default_row_group_size() -> usize {
+    10_000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_jitter_frame_ms() -> u32 {
+    20
+}
+
+fn default_jitter_shrink_after() -> u32 {
+    50
+}
+
+fn default_jitter_window() -> usize {
+    100
+}
+
+fn default_statsd_prefix() -> String {
+    "audio".to_string()
+}
+
+fn default_statsd_max_datagram() -> usize {
+    1432  // 1500 MTU minus IP/UDP headers, with headroom for tunnels
+}
+
+fn default_statsd_flush_ms() -> u64 {
+    1000
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MeasurementConfig {
@@ -7,18 +280,107 @@ pub struct MeasurementConfig {
     pub signature_rules: SignatureRules,
     pub metadata_extraction: MetadataExtraction,
     pub correlation: CorrelationConfig,
+    
+    /// Label dimensions on this measurement's latency metrics: "source_pod"
+    /// (where the signature was detected), "relay_pod" (where it was matched;
+    /// "pod" is an alias) or any id_type from metadata_extraction (e.g.
+    /// "interval_id", "ssrc")
+    #[serde(default = "default_metric_labels")]
+    pub metric_labels: Vec<String>,
+}
+
+fn default_metric_labels() -> Vec<String> {
+    vec!["interval_id".to_string(), "source_pod".to_string(), "relay_pod".to_string()]
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SignatureRules {
-    /// PxL filter to identify candidate streams
+    /// PxL filter to identify candidate streams, inline or `file://path/to/script.pxl`
     pub stream_filter: String,
     
     /// Audio detection criteria
     pub audio_criteria: AudioCriteria,
     
-    /// How often to sample (every N packets)
+    /// How often to run VAD (every N packets); the fingerprint itself is
+    /// taken over every packet's audio
     pub sampling_rate: u32,
+    
+    /// Detector threads per measurement; packets are sharded by connection
+    #[serde(default = "default_detector_workers")]
+    pub detector_workers: usize,
+    
+    /// Packets queued per detector shard before the stream loop waits
+    #[serde(default = "default_shard_queue_depth")]
+    pub shard_queue_depth: usize,
+    
+    /// A stream's detector (buffer, warmup, interval metadata) is dropped
+    /// after this many seconds without a packet
+    #[serde(default = "default_stream_idle_secs")]
+    pub stream_idle_secs: u64,
+    
+    /// Clear buffered audio when a packet's interval_id changes, so one
+    /// call's tail can't leak into the next call's detection window
+    #[serde(default)]
+    pub reset_per_interval: bool,
+    
+    /// Packets at the start of each stream and interval that build buffer
+    /// state but never emit a signature (connection setup, partial frames)
+    #[serde(default)]
+    pub warmup_packets: u32,
+    
+    /// Cap on signatures broadcast per second; excess is dropped and counted
+    /// so a noisy stream can't crowd out the shared channel
+    #[serde(default)]
+    pub max_signatures_per_sec: Option<f64>,
+    
+    /// Signatures allowed in a burst above the steady rate (defaults to one second's worth)
+    #[serde(default)]
+    pub signature_burst: Option<f64>,
+    
+    /// Seconds without a single row from stream_filter before warning that
+    /// the filter may be broken (wrong port or table) rather than just quiet
+    #[serde(default = "default_idle_warning_secs")]
+    pub idle_warning_secs: u64,
+    
+    /// Seconds without rows before the Pixie stream is presumed dead and
+    /// reconnected; shorter stalls are waited out. Unset never reconnects.
+    #[serde(default)]
+    pub stream_grace_secs: Option<u64>,
+    
+    /// What the detector does when its audio buffer reaches capacity
+    #[serde(default)]
+    pub buffer_full: BufferFullPolicy,
+}
+
+/// Audio buffer overflow handling (the buffer holds 50 sampled packets)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BufferFullPolicy {
+    /// Drop the oldest packet; fine while min_duration_ms fits in the buffer
+    #[default]
+    FifoDrop,
+    /// Treat a full buffer as one window: run VAD over it, emit a signature
+    /// if it qualifies, and start buffering afresh
+    EmitOnFull,
+    /// Keep older packets past capacity until min_duration_ms is covered,
+    /// so VAD judges a long signature's whole window
+    GrowToDuration,
+}
+
+fn default_detector_workers() -> usize {
+    1
+}
+
+fn default_shard_queue_depth() -> usize {
+    1024
+}
+
+fn default_stream_idle_secs() -> u64 {
+    300
+}
+
+fn default_idle_warning_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -34,6 +396,121 @@ pub struct AudioCriteria {
     
     /// Optional frequency range for speech detection
     pub frequency_range: Option<(f32, f32)>,
+    
+    /// Band-limit audio to frequency_range (default 300-3400Hz) before energy
+    /// and zero-crossing VAD, so out-of-band noise can't trigger them
+    #[serde(default)]
+    pub bandpass: bool,
+    
+    /// PCM encoding of the payload audio
+    #[serde(default)]
+    pub sample_format: SampleFormat,
+    
+    /// Sample rate of the payload audio in Hz
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+    
+    /// Window applied to each frame before an FFT (spectral VAD, fingerprinting)
+    #[serde(default)]
+    pub window_function: WindowFunction,
+    
+    /// Energy VAD: length of the sliding RMS window in ms. Fires if any window
+    /// exceeds energy_threshold; unset averages over the whole buffer.
+    #[serde(default)]
+    pub rms_window_ms: Option<u32>,
+    
+    /// Audio that must be buffered before VAD may fire, in ms, so a first
+    /// tiny chunk can't produce a signature; unset uses min_duration_ms
+    #[serde(default)]
+    pub min_buffered_ms: Option<u32>,
+    
+    /// RTP measurements: decode format per payload type, for dynamic PTs
+    /// (96-127) or to override the static table (0 = PCMU, 8 = PCMA, 10/11 = L16)
+    #[serde(default)]
+    pub rtp_payload_types: HashMap<u8, AudioFormat>,
+    
+    /// What to do with payloads that aren't a whole number of sample frames
+    #[serde(default)]
+    pub frame_alignment: FrameAlignment,
+    
+    /// Whether payloads are SRTP ciphertext, on which VAD would only see noise
+    #[serde(default)]
+    pub encryption: PayloadEncryption,
+}
+
+fn default_sample_rate() -> u32 {
+    8000
+}
+
+/// How to turn payload bytes into mono samples
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AudioFormat {
+    pub sample_format: SampleFormat,
+    pub sample_rate: u32,
+    /// Interleaved channels, averaged to mono before analysis
+    #[serde(default = "default_channels")]
+    pub channels: u16,
+}
+
+fn default_channels() -> u16 {
+    1
+}
+
+/// Encrypted payloads skip content analysis; signatures come from talkspurt
+/// timing (the first packet after a silence gap) instead
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub enum PayloadEncryption {
+    /// Plain audio: always run VAD
+    #[default]
+    Plaintext,
+    /// Always SRTP
+    Srtp,
+    /// Switch to timing-only once payloads look consistently random
+    Detect,
+}
+
+/// Handling of payloads that end mid-frame (e.g. 99 bytes of 16-bit audio).
+/// Misaligned payloads are counted either way.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub enum FrameAlignment {
+    /// Carry the partial frame over to the start of the next payload, so the
+    /// stream stays aligned across arbitrary chunk boundaries
+    #[default]
+    Realign,
+    /// Decode each payload on its own, dropping the partial frame
+    Count,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub enum WindowFunction {
+    /// No tapering; best frequency resolution, worst leakage
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    /// Lowest leakage, widest main lobe
+    Blackman,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub enum SampleFormat {
+    /// 16-bit signed little-endian
+    #[default]
+    S16Le,
+    /// 16-bit signed big-endian (RTP L16)
+    S16Be,
+    /// 24-bit signed packed little-endian (3 bytes per sample)
+    S24Le,
+    /// 24-bit signed packed big-endian (RTP L24)
+    S24Be,
+    /// G.711 μ-law (RTP PCMU)
+    MuLaw,
+    /// G.711 A-law (RTP PCMA)
+    ALaw,
+    /// 32-bit IEEE float little-endian, -1.0..1.0 (common with WebRTC)
+    F32Le,
+    /// 32-bit IEEE float big-endian
+    F32Be,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -46,6 +523,17 @@ pub enum VadMode {
     Spectral,
     /// ML-based VAD (requires model)
     ML { model_path: String },
+    /// Injected reference tone: signature at the tone's onset (Goertzel filter)
+    Tone {
+        frequency_hz: f32,
+        /// Share of block energy at frequency_hz needed to count as tone present
+        #[serde(default = "default_tone_threshold")]
+        threshold: f32,
+    },
+}
+
+fn default_tone_threshold() -> f32 {
+    0.5
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -53,11 +541,30 @@ pub struct MetadataExtraction {
     /// Packet offset where metadata typically appears
     pub header_offset: usize,
     
-    /// Pattern to find UUID/segment ID
+    /// Patterns to find UUID/segment IDs, highest priority first: for each
+    /// id_type the first pattern that matches wins (primary, then fallbacks)
     pub id_patterns: Vec<IdPattern>,
     
     /// Protocol-specific parsing
     pub protocol: ProtocolType,
+    
+    /// What to do with a signature lacking any of its correlation.grouping_key ids
+    #[serde(default)]
+    pub missing_metadata_policy: MissingMetadataPolicy,
+}
+
+/// Handling of signatures whose grouping ids never turned up. Every policy
+/// counts them in signatures_missing_metadata_total.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingMetadataPolicy {
+    /// Stop the measurement: its id patterns no longer fit the traffic
+    Error,
+    /// Drop the signature
+    Skip,
+    /// Broadcast it; matches land in the "unknown" group
+    #[default]
+    EmitAnyway,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -73,6 +580,33 @@ pub struct IdPattern {
     
     /// Length of the ID value
     pub value_length: usize,
+    
+    /// How a binary match's value bytes become the string ID
+    #[serde(default)]
+    pub encoding: IdEncoding,
+    
+    /// Confine the search to payload bytes [search_start, search_end) when the
+    /// ID's location is known, so large packets aren't scanned and decoys
+    /// elsewhere can't match. Defaults: binary patterns search from byte 0,
+    /// regexes from header_offset; both to the end of the payload.
+    #[serde(default)]
+    pub search_start: Option<usize>,
+    
+    #[serde(default)]
+    pub search_end: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdEncoding {
+    /// Text ID (lossy for invalid UTF-8)
+    #[default]
+    Utf8,
+    /// Raw bytes as lowercase hex
+    Hex,
+    U32Be,
+    U32Le,
+    U64Be,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -103,6 +637,49 @@ pub struct CorrelationConfig {
     /// Maximum concurrent measurements
     pub max_active_signatures: usize,
     
-    /// How to group related measurements
-    pub grouping_key: String, // e.g., "interval_id"
-}
\ No newline at end of file
+    /// Metadata field(s) that group related matches, e.g. "interval_id" or a
+    /// composite "call_id,channel"
+    pub grouping_key: String,
+    
+    /// Signatures shorter than this are fingerprinted from too little audio to
+    /// match reliably; the matcher never stores them. Tone onsets are exempt.
+    #[serde(default)]
+    pub min_signature_duration_ms: u32,
+    
+    /// Regex an interval_id must match to be measured at all; other intervals'
+    /// packets are dropped before buffering. Unset measures every interval.
+    #[serde(default)]
+    pub interval_id_allowlist: Option<String>,
+    
+    /// After a signature matches, further matches of it within this many ms
+    /// are the same event seen again (overlapping query windows, repeated
+    /// packets) and are dropped rather than recorded twice. 0 disables.
+    #[serde(default)]
+    pub match_dedup_ms: u64,
+}
+
+/// Minimal measurement for unit tests: raw 8kHz S16Le audio, energy VAD on
+/// every packet, 200ms fingerprints. Tests adjust fields from here.
+#[cfg(test)]
+pub fn test_measurement() -> MeasurementConfig {
+    serde_yaml::from_str(r#"
+        name: test
+        enabled: true
+        signature_rules:
+          stream_filter: "df = px.DataFrame(table='socket_data')"
+          audio_criteria:
+            min_duration_ms: 200
+            energy_threshold: 0.01
+            vad_mode: Energy
+            sample_rate: 8000
+          sampling_rate: 1
+        metadata_extraction:
+          header_offset: 0
+          id_patterns: []
+          protocol: !Custom { parser_script: "" }
+        correlation:
+          signature_ttl_seconds: 60
+          max_active_signatures: 100
+          grouping_key: interval_id
+    "#).unwrap()
+}
@@ -0,0 +1,207 @@
+use crate::config::MeasurementConfig;
+use crate::metrics::SensorMetrics;
+use crate::signature_detector::{DetectorError, SignatureDetector, SignatureEvent};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How often a worker sweeps its idle streams
+const SWEEP_EVERY: Duration = Duration::from_secs(1);
+
+/// (stream key, payload, captured_at_ns, pod)
+type Job = (String, Vec<u8>, u64, String);
+
+/// Callback invoked on the worker thread for every detected signature
+pub type SignatureSink = Arc<dyn Fn(SignatureEvent) + Send + Sync>;
+
+/// Fans packets out to N worker threads, sharded by stream. Each worker keeps
+/// one detector per stream key, so a stream's buffer, frame alignment, warmup
+/// and interval metadata only ever see that stream's packets, in order.
+pub struct DetectorPool {
+    shards: Vec<mpsc::Sender<Job>>,
+    /// First error any worker stopped on; fails the whole measurement
+    failure: Arc<OnceLock<DetectorError>>,
+}
+
+impl DetectorPool {
+    pub fn new(config: MeasurementConfig, hash_seed: u64, metrics: Arc<SensorMetrics>, sink: SignatureSink) -> Self {
+        let workers = config.signature_rules.detector_workers.max(1);
+        let mut shards = Vec::with_capacity(workers);
+        let failure = Arc::new(OnceLock::new());
+        
+        for shard in 0..workers {
+            let (tx, mut rx) = mpsc::channel::<Job>(config.signature_rules.shard_queue_depth);
+            let config = config.clone();
+            let sink = sink.clone();
+            let metrics = metrics.clone();
+            let failure = failure.clone();
+            let idle_after = Duration::from_secs(config.signature_rules.stream_idle_secs);
+            
+            // VAD is CPU-bound, so workers run on dedicated threads, not the async runtime
+            std::thread::Builder::new()
+                .name(format!("{}-detector-{}", config.name, shard))
+                .spawn(move || {
+                    let mut detectors: HashMap<String, (SignatureDetector, Instant)> = HashMap::new();
+                    let mut swept_at = Instant::now();
+                    
+                    while let Some((stream_key, payload, captured_at_ns, pod)) = rx.blocking_recv() {
+                        // Ended calls and closed connections never say so; forget them once quiet
+                        if swept_at.elapsed() >= SWEEP_EVERY {
+                            detectors.retain(|_, (_, last_seen)| last_seen.elapsed() < idle_after);
+                            swept_at = Instant::now();
+                        }
+                        
+                        let (detector, last_seen) = detectors
+                            .entry(stream_key)
+                            .or_insert_with(|| (SignatureDetector::new(config.clone(), hash_seed, &metrics), Instant::now()));
+                        *last_seen = Instant::now();
+                        
+                        match detector.process_packet(&payload, captured_at_ns) {
+                            Ok(Some(mut sig_event)) => {
+                                sig_event.source_pod = Some(pod);
+                                sink(sig_event);
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                let _ = failure.set(e);
+                                break;
+                            }
+                        }
+                    }
+                })
+                .expect("failed to spawn detector worker");
+            
+            shards.push(tx);
+        }
+        
+        Self { shards, failure }
+    }
+    
+    /// Queue a packet on the shard owning `stream_key` (connection id, interval, ...).
+    /// `pod` is where it was captured, stamped on any resulting signature.
+    /// Waits when that shard is backed up rather than dropping packets.
+    /// Errors once any worker has stopped, on a DetectorError or a panic.
+    pub async fn submit(
+        &self,
+        stream_key: &str,
+        payload: Vec<u8>,
+        captured_at_ns: u64,
+        pod: &str,
+    ) -> Result<(), DetectorError> {
+        if let Some(e) = self.failure.get() {
+            return Err(e.clone());
+        }
+        let shard = (xxhash_rust::xxh3::xxh3_64(stream_key.as_bytes()) % self.shards.len() as u64) as usize;
+        
+        // A closed shard means its worker died (panicked); the measurement can't continue
+        self.shards[shard]
+            .send((stream_key.to_string(), payload, captured_at_ns, pod.to_string()))
+            .await
+            .map_err(|_| DetectorError::WorkerStopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{test_measurement, SampleFormat};
+    use crate::metrics::test_metrics;
+    use crate::sample_decode::encode_samples;
+    use std::sync::Mutex;
+    
+    /// 20ms S16Le packets of a tone whose level moves at `swell` Hz
+    fn packets(swell: f32) -> Vec<Vec<u8>> {
+        let samples: Vec<f32> = (0..8000)
+            .map(|i| {
+                let t = i as f32 / 8000.0;
+                (t * 440.0 * std::f32::consts::TAU).sin() * (0.05 + 0.45 * (t * swell).sin().abs())
+            })
+            .collect();
+        encode_samples(&samples, &SampleFormat::S16Le).chunks(320).map(<[u8]>::to_vec).collect()
+    }
+    
+    fn hashes_alone(packets: &[Vec<u8>]) -> Vec<u64> {
+        let mut detector = SignatureDetector::new(test_measurement(), 0, &test_metrics());
+        packets.iter()
+            .filter_map(|packet| detector.process_packet(packet, 0).unwrap())
+            .map(|event| event.signature.hash)
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn interleaved_streams_keep_separate_state() {
+        let (a, b) = (packets(3.0), packets(7.0));
+        let seen: Arc<Mutex<Vec<(String, u64)>>> = Arc::default();
+        let sink_seen = seen.clone();
+        let sink: SignatureSink = Arc::new(move |event: SignatureEvent| {
+            sink_seen.lock().unwrap().push((event.source_pod.unwrap(), event.signature.hash));
+        });
+        
+        // One worker, so both streams share a thread
+        let pool = DetectorPool::new(test_measurement(), 0, Arc::new(test_metrics()), sink);
+        for (packet_a, packet_b) in a.iter().zip(&b) {
+            pool.submit("a", packet_a.clone(), 0, "pod-a").await.unwrap();
+            pool.submit("b", packet_b.clone(), 0, "pod-b").await.unwrap();
+        }
+        
+        let expected = hashes_alone(&a).len() + hashes_alone(&b).len();
+        assert!(hashes_alone(&a).len() > 1);
+        for _ in 0..200 {
+            if seen.lock().unwrap().len() >= expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        
+        let seen = seen.lock().unwrap();
+        let of = |pod: &str| -> Vec<u64> {
+            seen.iter().filter(|(p, _)| p == pod).map(|(_, hash)| *hash).collect()
+        };
+        assert_eq!(of("pod-a"), hashes_alone(&a));
+        assert_eq!(of("pod-b"), hashes_alone(&b));
+    }
+    
+    #[tokio::test]
+    async fn a_blocked_sink_holds_back_submit() {
+        // The sink stands in for a full local_signature_queue: it blocks until released
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let sink: SignatureSink = Arc::new(move |_| {
+            let _ = released.lock().unwrap().recv();
+        });
+        let mut config = test_measurement();
+        config.signature_rules.shard_queue_depth = 1;
+        let pool = Arc::new(DetectorPool::new(config, 0, Arc::new(test_metrics()), sink));
+        
+        let submitter = pool.clone();
+        let mut feeding = tokio::spawn(async move {
+            for packet in packets(3.0) {
+                submitter.submit("a", packet, 0, "pod-a").await.unwrap();
+            }
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(200), &mut feeding).await.is_err());
+        
+        drop(release);
+        tokio::time::timeout(Duration::from_secs(5), feeding).await.unwrap().unwrap();
+    }
+    
+    #[tokio::test]
+    async fn a_worker_that_died_fails_submit() {
+        let pool = DetectorPool::new(
+            test_measurement(), 0, Arc::new(test_metrics()), Arc::new(|_| panic!("sink failed")),
+        );
+        // 20ms at half of full scale: a signature once 200ms is buffered
+        let loud = [0x00, 0x40].repeat(160);
+        
+        let mut result = Ok(());
+        for _ in 0..1000 {
+            result = pool.submit("stream", loud.clone(), 0, "pod").await;
+            if result.is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(matches!(result, Err(DetectorError::WorkerStopped)), "{:?}", result);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::config::{AudioFormat, FrameAlignment, MeasurementConfig, PayloadEncryption, VadMode};
+use crate::rtp;
+use crate::sample_decode::{decode_samples, downmix};
+use crate::signature_detector::SignatureEvent;
+use crate::signature_store::SignatureStore;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Fingerprints analyse audio at this rate whatever the codec's, so hops that
+/// transcode between rates still agree
+pub const FINGERPRINT_RATE: u32 = 8000;
+
+/// Fingerprint analysis frame; one quantized level per frame goes into the hash
+pub const FRAME_MS: usize = 20;
+
+/// Samples per frame at FINGERPRINT_RATE
+const FRAME_LEN: usize = FINGERPRINT_RATE as usize * FRAME_MS / 1000;
+
+/// Fingerprint window when a measurement sets no min_duration_ms
+const DEFAULT_WINDOW_MS: usize = 500;
+
+/// Level quantization step; coarse enough that codec noise rarely crosses a step
+const LEVEL_STEP_DB: f32 = 3.0;
+
+/// Frames in a measurement's fingerprint: min_duration_ms of audio, or
+/// DEFAULT_WINDOW_MS when unset. Detector and matcher both derive it from
+/// config, so they hash windows of the same length.
+pub fn window_frames(min_duration_ms: u32) -> usize {
+    match min_duration_ms as usize {
+        0 => DEFAULT_WINDOW_MS / FRAME_MS,
+        ms => (ms / FRAME_MS).max(1),
+    }
+}
+
+/// Hash of a fingerprint's frame levels under the deployment seed
+pub fn hash(levels: &[u8], seed: u64) -> u64 {
+    xxhash_rust::xxh3::xxh3_64_with_seed(levels, seed)
+}
+
+/// Rolling energy profile of one audio stream. Every decoded sample goes in,
+/// resampled to FINGERPRINT_RATE; a fingerprint is the level of each frame
+/// counted back from the newest sample. Packet boundaries never enter into
+/// it, so a hop re-packetizing the same audio (20ms in, 10ms out) still
+/// produces the source's fingerprint at the packet ending where the source's did.
+pub struct Fingerprinter {
+    /// Input samples per resampled sample
+    step: f64,
+    /// Input position (samples since the stream started) of the next resampled sample
+    next_at: f64,
+    consumed: u64,
+    previous: f32,
+    /// Newest resampled audio, at most `capacity` samples
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl Fingerprinter {
+    /// Keeps enough audio for fingerprints of up to `max_frames` frames
+    pub fn new(sample_rate: u32, max_frames: usize) -> Self {
+        let capacity = max_frames.max(1) * FRAME_LEN;
+        Self {
+            step: sample_rate.max(1) as f64 / FINGERPRINT_RATE as f64,
+            next_at: 0.0,
+            consumed: 0,
+            previous: 0.0,
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    
+    /// Append decoded mono samples, linearly interpolated onto FINGERPRINT_RATE
+    pub fn push(&mut self, input: &[f32]) {
+        for &sample in input {
+            let position = self.consumed as f64;
+            // Resampled points between the previous input sample and this one
+            while self.next_at <= position {
+                let frac = (self.next_at - (position - 1.0)) as f32;
+                self.samples.push_back(self.previous + (sample - self.previous) * frac);
+                if self.samples.len() > self.capacity {
+                    self.samples.pop_front();
+                }
+                self.next_at += self.step;
+            }
+            self.previous = sample;
+            self.consumed += 1;
+        }
+    }
+    
+    /// Frame levels of the newest `frames` frames; None until that much audio has arrived
+    pub fn fingerprint(&self, frames: usize) -> Option<Vec<u8>> {
+        let needed = frames * FRAME_LEN;
+        if frames == 0 || self.samples.len() < needed {
+            return None;
+        }
+        
+        let window: Vec<f32> = self.samples.range(self.samples.len() - needed..).copied().collect();
+        Some(window.chunks_exact(FRAME_LEN).map(frame_level).collect())
+    }
+    
+    /// Forget buffered audio (new format, interval or encryption state)
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// RMS level of a fingerprint frame in LEVEL_STEP_DB steps below full scale
+fn frame_level(frame: &[f32]) -> u8 {
+    let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len().max(1) as f32).sqrt();
+    let db = 20.0 * rms.max(1e-5).log10();
+    (-db / LEVEL_STEP_DB).round() as u8
+}
+
+/// Whole sample frames of `audio` under `alignment`: Realign prefixes the
+/// previous payload's partial frame (held in `carry`) and holds back this
+/// one's; Count passes the payload through as-is
+pub fn align_frames<'a>(
+    carry: &mut Vec<u8>,
+    audio: &'a [u8],
+    frame_bytes: usize,
+    alignment: &FrameAlignment,
+) -> Cow<'a, [u8]> {
+    match alignment {
+        FrameAlignment::Count => Cow::Borrowed(audio),
+        FrameAlignment::Realign if carry.is_empty() && audio.len().is_multiple_of(frame_bytes) => {
+            Cow::Borrowed(audio)
+        }
+        FrameAlignment::Realign => {
+            let mut joined = std::mem::take(carry);
+            joined.extend_from_slice(audio);
+            let whole = joined.len() - joined.len() % frame_bytes;
+            *carry = joined.split_off(whole);
+            Cow::Owned(joined)
+        }
+    }
+}
+
+/// One relayed stream as seen by one measurement
+struct RelayStream {
+    fingerprinter: Fingerprinter,
+    format: AudioFormat,
+    frame_carry: Vec<u8>,
+    last_seen: Instant,
+}
+
+/// Matcher side of content fingerprints: runs each relayed stream through the
+/// detector's decode and fingerprint pipeline, per measurement, and claims the
+/// stored signature whose fingerprint the stream reproduces
+pub struct FingerprintMatcher {
+    // (measurement, fingerprint frames)
+    measurements: Vec<(MeasurementConfig, usize)>,
+    // (measurement, stream) -> state
+    streams: HashMap<(String, String), RelayStream>,
+}
+
+impl FingerprintMatcher {
+    /// Tone measurements match on onsets and SRTP ones on packet timing;
+    /// every other enabled measurement fingerprints content
+    pub fn new(measurements: &[MeasurementConfig]) -> Self {
+        let measurements = measurements.iter()
+            .filter(|m| m.enabled)
+            .filter(|m| {
+                let criteria = &m.signature_rules.audio_criteria;
+                !matches!(criteria.vad_mode, VadMode::Tone { .. })
+                    && !matches!(criteria.encryption, PayloadEncryption::Srtp)
+            })
+            .map(|m| (m.clone(), window_frames(m.signature_rules.audio_criteria.min_duration_ms)))
+            .collect();
+        
+        Self { measurements, streams: HashMap::new() }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.measurements.is_empty()
+    }
+    
+    /// Feed one relayed payload from `stream`; returns the signatures it
+    /// completed, each with the relay-side fingerprint that matched
+    pub fn observe(
+        &mut self,
+        stream: &str,
+        payload: &[u8],
+        store: &SignatureStore,
+        hash_seed: u64,
+    ) -> Vec<(SignatureEvent, Vec<u8>)> {
+        let mut matched = Vec::new();
+        
+        for (measurement, frames) in &self.measurements {
+            let Some((audio, format)) = rtp::audio_payload(payload, measurement) else {
+                continue;
+            };
+            
+            let state = self.streams
+                .entry((measurement.name.clone(), stream.to_string()))
+                .or_insert_with(|| RelayStream {
+                    fingerprinter: Fingerprinter::new(format.sample_rate, *frames),
+                    format: format.clone(),
+                    frame_carry: Vec::new(),
+                    last_seen: Instant::now(),
+                });
+            state.last_seen = Instant::now();
+            if state.format != format {
+                // Payload type changed mid-stream; buffered audio no longer decodes alike
+                state.fingerprinter = Fingerprinter::new(format.sample_rate, *frames);
+                state.frame_carry.clear();
+                state.format = format.clone();
+            }
+            
+            let frame_bytes = format.sample_format.bytes_per_sample() * format.channels.max(1) as usize;
+            let alignment = &measurement.signature_rules.audio_criteria.frame_alignment;
+            let audio = align_frames(&mut state.frame_carry, audio, frame_bytes, alignment);
+            state.fingerprinter.push(&downmix(decode_samples(&audio, &format.sample_format), format.channels));
+            
+            if let Some(levels) = state.fingerprinter.fingerprint(*frames) {
+                // Same audio tracked by another measurement still correlates
+                if let Some(event) = store.take_any(hash(&levels, hash_seed), Some(&measurement.name)) {
+                    matched.push((event, levels));
+                }
+            }
+        }
+        
+        matched
+    }
+    
+    /// Drop streams silent for longer than their measurement's signature TTL:
+    /// no signature they could still match is left in the store
+    pub fn prune_idle(&mut self) {
+        let ttls: HashMap<&str, Duration> = self.measurements.iter()
+            .map(|(m, _)| (m.name.as_str(), Duration::from_secs(m.correlation.signature_ttl_seconds)))
+            .collect();
+        self.streams.retain(|(measurement, _), state| {
+            ttls.get(measurement.as_str()).is_some_and(|ttl| state.last_seen.elapsed() < *ttl)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{test_measurement, SampleFormat};
+    use crate::metrics::test_metrics;
+    use crate::sample_decode::encode_samples;
+    use crate::signature_detector::SignatureDetector;
+    
+    /// Tone whose level swells and fades, so consecutive frames differ
+    fn speech_like(rate: u32, ms: usize) -> Vec<f32> {
+        (0..rate as usize * ms / 1000)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                (t * 440.0 * std::f32::consts::TAU).sin() * (0.05 + 0.45 * (t * 3.0).sin().abs())
+            })
+            .collect()
+    }
+    
+    fn fingerprint_in_chunks(samples: &[f32], rate: u32, chunk_ms: usize) -> Option<Vec<u8>> {
+        let mut fingerprinter = Fingerprinter::new(rate, 10);
+        for chunk in samples.chunks(rate as usize * chunk_ms / 1000) {
+            fingerprinter.push(chunk);
+        }
+        fingerprinter.fingerprint(10)
+    }
+    
+    #[test]
+    fn window_follows_min_duration() {
+        assert_eq!(window_frames(500), 25);
+        assert_eq!(window_frames(10), 1);
+        assert_eq!(window_frames(0), DEFAULT_WINDOW_MS / FRAME_MS);
+    }
+    
+    #[test]
+    fn packetization_does_not_change_the_fingerprint() {
+        for rate in [8000, 16000, 48000] {
+            let audio = speech_like(rate, 400);
+            let by_10ms = fingerprint_in_chunks(&audio, rate, 10).unwrap();
+            assert_eq!(by_10ms.len(), 10);
+            assert_eq!(Some(&by_10ms), fingerprint_in_chunks(&audio, rate, 20).as_ref(), "{}Hz", rate);
+            assert_eq!(Some(&by_10ms), fingerprint_in_chunks(&audio, rate, 40).as_ref(), "{}Hz", rate);
+        }
+    }
+    
+    #[test]
+    fn fingerprint_needs_a_full_window() {
+        let mut fingerprinter = Fingerprinter::new(8000, 10);
+        fingerprinter.push(&speech_like(8000, 190));
+        assert!(fingerprinter.fingerprint(10).is_none());
+        fingerprinter.push(&speech_like(8000, 10));
+        assert!(fingerprinter.fingerprint(10).is_some());
+        fingerprinter.clear();
+        assert!(fingerprinter.fingerprint(1).is_none());
+    }
+    
+    #[test]
+    fn realign_carries_partial_frames() {
+        let mut carry = Vec::new();
+        let first = align_frames(&mut carry, &[1, 2, 3], 2, &FrameAlignment::Realign);
+        assert_eq!(&*first, &[1, 2]);
+        let second = align_frames(&mut carry, &[4, 5, 6], 2, &FrameAlignment::Realign);
+        assert_eq!(&*second, &[3, 4, 5, 6]);
+        assert!(carry.is_empty());
+        assert_eq!(&*align_frames(&mut carry, &[7, 8, 9], 2, &FrameAlignment::Count), &[7, 8, 9]);
+    }
+    
+    #[test]
+    fn relay_finds_a_repacketized_signature() {
+        let measurement = test_measurement();
+        let audio = encode_samples(&speech_like(8000, 600), &SampleFormat::S16Le);
+        let store = SignatureStore::new();
+        
+        // Source: 20ms packets; keep the first signature
+        let mut detector = SignatureDetector::new(measurement.clone(), 7, &test_metrics());
+        let mut source_end = 0;
+        for (i, packet) in audio.chunks(320).enumerate() {
+            if let Some(event) = detector.detect(packet, i as u64) {
+                source_end = (i + 1) * 320;
+                store.insert(event);
+                break;
+            }
+        }
+        assert_eq!(store.len(), 1);
+        
+        // Relay: the same audio in 10ms packets
+        let mut matcher = FingerprintMatcher::new(std::slice::from_ref(&measurement));
+        let mut matched_at = None;
+        for (i, packet) in audio.chunks(160).enumerate() {
+            if let Some((event, levels)) = matcher.observe("relay", packet, &store, 7).pop() {
+                assert_eq!(levels, event.signature.fingerprint);
+                matched_at = Some((i + 1) * 160);
+                break;
+            }
+        }
+        assert_eq!(matched_at, Some(source_end));
+        assert!(store.is_empty());
+    }
+    
+    #[test]
+    fn idle_relay_streams_are_pruned() {
+        let mut measurement = test_measurement();
+        measurement.correlation.signature_ttl_seconds = 0;
+        let mut matcher = FingerprintMatcher::new(&[measurement]);
+        matcher.observe("relay", &encode_samples(&speech_like(8000, 20), &SampleFormat::S16Le), &SignatureStore::new(), 0);
+        assert_eq!(matcher.streams.len(), 1);
+        
+        matcher.prune_idle();
+        assert!(matcher.streams.is_empty());
+    }
+    
+    #[test]
+    fn seeds_partition_the_hash_space() {
+        let measurement = test_measurement();
+        let audio = encode_samples(&speech_like(8000, 600), &SampleFormat::S16Le);
+        let first_event = |seed| {
+            let mut detector = SignatureDetector::new(measurement.clone(), seed, &test_metrics());
+            audio.chunks(320).enumerate().find_map(|(i, packet)| detector.detect(packet, i as u64)).unwrap()
+        };
+        
+        let (seven, eight) = (first_event(7), first_event(8));
+        assert_eq!(seven.signature.fingerprint, eight.signature.fingerprint);
+        assert_ne!(seven.signature.hash, eight.signature.hash);
+        assert_eq!((seven.signature.seed, eight.signature.seed), (7, 8));
+        
+        // A relay hashing under another deployment's seed never matches
+        let store = SignatureStore::new();
+        store.insert(seven);
+        let mut matcher = FingerprintMatcher::new(std::slice::from_ref(&measurement));
+        assert!(audio.chunks(320).all(|packet| matcher.observe("relay", packet, &store, 8).is_empty()));
+        assert_eq!(store.len(), 1);
+    }
+    
+    #[test]
+    fn frame_levels_are_rms_whatever_the_sample_format() {
+        // Full-scale sine: RMS -3dB, one step; a tenth of that, -23dB
+        let full: Vec<f32> = (0..FRAME_LEN).map(|i| (i as f32 / 8.0 * std::f32::consts::TAU).sin()).collect();
+        assert_eq!(frame_level(&full), 1);
+        assert_eq!(frame_level(&full.iter().map(|s| s * 0.1).collect::<Vec<_>>()), 8);
+        
+        let audio = speech_like(8000, 200);
+        let levels: Vec<Vec<u8>> = [SampleFormat::S16Le, SampleFormat::S16Be, SampleFormat::S24Le, SampleFormat::F32Le]
+            .iter()
+            .map(|format| {
+                let mut fingerprinter = Fingerprinter::new(8000, 10);
+                fingerprinter.push(&decode_samples(&encode_samples(&audio, format), format));
+                fingerprinter.fingerprint(10).unwrap()
+            })
+            .collect();
+        assert!(levels.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", levels);
+    }
+}
@@ -0,0 +1,250 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use crate::openmetrics::{self, Exemplars};
+use crate::toggles::MeasurementToggles;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use prometheus::Registry;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// One successful correlation, pushed to live-feed subscribers as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchRecord {
+    pub measurement: String,
+    pub interval_id: String,
+    pub latency_ms: f64,
+    pub source_pod: Option<String>,
+    pub relay_pod: String,
+    /// Wall-clock time the match was recorded (unix ms)
+    pub matched_at_ms: u64,
+    /// Hex of the bytes hashed on each side, with debug_fingerprints only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_fingerprint: Option<String>,
+}
+
+impl MatchRecord {
+    /// Attach both sides' fingerprints, for seeing why two signatures matched
+    pub fn with_fingerprints(mut self, source: &[u8], relay: &[u8]) -> Self {
+        self.source_fingerprint = Some(hex(source));
+        self.relay_fingerprint = Some(hex(relay));
+        self
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Body of POST /measurements/{name}/enabled
+#[derive(Debug, Deserialize)]
+struct EnabledRequest {
+    enabled: bool,
+}
+
+#[derive(Clone)]
+struct AppState {
+    registry: Registry,
+    matches: broadcast::Sender<MatchRecord>,
+    toggles: MeasurementToggles,
+    /// Set: render OpenMetrics with these exemplars instead of Prometheus text
+    exemplars: Option<Arc<Exemplars>>,
+}
+
+/// Serve `registry`'s metrics, the live match feed and the runtime
+/// measurement switches until the listener fails
+pub async fn serve(
+    listen_addr: &str,
+    registry: Registry,
+    matches: broadcast::Sender<MatchRecord>,
+    toggles: MeasurementToggles,
+    exemplars: Option<Arc<Exemplars>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .route("/matches", get(match_feed))
+        .route("/measurements/:name/enabled", post(set_enabled))
+        .with_state(AppState { registry, matches, toggles, exemplars });
+    
+    axum::Server::bind(&listen_addr.parse()?)
+        .serve(app.into_make_service())
+        .await?;
+    
+    Ok(())
+}
+
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let families = state.registry.gather();
+    match &state.exemplars {
+        Some(exemplars) => (
+            [(header::CONTENT_TYPE, openmetrics::CONTENT_TYPE)],
+            openmetrics::encode(&families, exemplars),
+        ),
+        None => (
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            prometheus::TextEncoder::new().encode_to_string(&families).unwrap_or_default(),
+        ),
+    }
+}
+
+async fn set_enabled(
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<EnabledRequest>,
+) -> StatusCode {
+    if !state.toggles.set(&name, request.enabled) {
+        return StatusCode::NOT_FOUND;
+    }
+    
+    println!("🔀 Measurement {} {} via HTTP", name, if request.enabled { "enabled" } else { "disabled" });
+    StatusCode::NO_CONTENT
+}
+
+async fn match_feed(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+    let matches = state.matches.subscribe();
+    ws.on_upgrade(move |socket| stream_matches(socket, matches))
+}
+
+async fn stream_matches(mut socket: WebSocket, mut matches: broadcast::Receiver<MatchRecord>) {
+    loop {
+        let record = match matches.recv().await {
+            Ok(record) => record,
+            // A slow client just misses some records; keep it connected
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        
+        let Ok(json) = serde_json::to_string(&record) else { continue };
+        if socket.send(Message::Text(json)).await.is_err() {
+            break;  // Client went away
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    use crate::metrics::test_metrics;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    
+    fn record() -> MatchRecord {
+        MatchRecord {
+            measurement: "calls".to_string(),
+            interval_id: "42".to_string(),
+            latency_ms: 12.5,
+            source_pod: Some("source-0".to_string()),
+            relay_pod: "relay-0".to_string(),
+            matched_at_ms: 1_700_000_000_000,
+            source_fingerprint: None,
+            relay_fingerprint: None,
+        }
+    }
+    
+    /// Serve on a free local port; returns once it accepts connections
+    async fn start(
+        matches: broadcast::Sender<MatchRecord>,
+        toggles: MeasurementToggles,
+        exemplars: Option<Arc<Exemplars>>,
+    ) -> (SocketAddr, Registry) {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let registry = Registry::new();
+        let served = registry.clone();
+        tokio::spawn(async move {
+            let _ = serve(&addr.to_string(), served, matches, toggles, exemplars).await;
+        });
+        
+        for _ in 0..100 {
+            if TcpStream::connect(addr).await.is_ok() {
+                return (addr, registry);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("server never came up on {}", addr);
+    }
+    
+    /// Read until the end of the HTTP response head
+    async fn response_head(stream: &mut TcpStream) -> String {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        String::from_utf8(head).unwrap()
+    }
+    
+    #[tokio::test]
+    async fn match_feed_pushes_records_as_json() {
+        let (match_tx, _) = broadcast::channel(16);
+        let toggles = MeasurementToggles::new(&[], &test_metrics());
+        let (addr, _) = start(match_tx.clone(), toggles, None).await;
+        
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(concat!(
+            "GET /matches HTTP/1.1\r\n",
+            "Host: localhost\r\n",
+            "Upgrade: websocket\r\n",
+            "Connection: Upgrade\r\n",
+            "Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n",
+            "Sec-WebSocket-Version: 13\r\n\r\n",
+        ).as_bytes()).await.unwrap();
+        assert!(response_head(&mut stream).await.starts_with("HTTP/1.1 101"));
+        
+        // Subscribed before the upgrade completed, so this one is delivered
+        match_tx.send(record()).unwrap();
+        
+        // Server frames are unmasked: FIN + text opcode, then the length
+        // (126: a 16-bit length follows)
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], 0x81);
+        let len = match header[1] {
+            126 => stream.read_u16().await.unwrap() as usize,
+            len => len as usize,
+        };
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        
+        let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(json["measurement"], "calls");
+        assert_eq!(json["latency_ms"], 12.5);
+        assert_eq!(json["source_pod"], "source-0");
+        assert_eq!(json["relay_pod"], "relay-0");
+    }
+    
+    #[tokio::test]
+    async fn measurements_switch_off_and_on_over_post() {
+        let toggles = MeasurementToggles::new(&[test_measurement()], &test_metrics());
+        let state = AppState {
+            registry: Registry::new(),
+            matches: broadcast::channel(16).0,
+            toggles: toggles.clone(),
+            exemplars: None,
+        };
+        let post = |name: &str, enabled: bool| {
+            set_enabled(Path(name.to_string()), State(state.clone()), Json(EnabledRequest { enabled }))
+        };
+        
+        assert_eq!(post("test", false).await, StatusCode::NO_CONTENT);
+        assert!(!toggles.is_enabled("test"));
+        assert_eq!(post("test", true).await, StatusCode::NO_CONTENT);
+        assert!(toggles.is_enabled("test"));
+        assert_eq!(post("absent", false).await, StatusCode::NOT_FOUND);
+    }
+    
+    #[test]
+    fn fingerprints_are_serialized_only_when_attached() {
+        let plain = serde_json::to_value(record()).unwrap();
+        assert!(plain.get("source_fingerprint").is_none() && plain.get("relay_fingerprint").is_none());
+        
+        let debug = serde_json::to_value(record().with_fingerprints(&[0x0a, 0xff], &[])).unwrap();
+        assert_eq!(debug["source_fingerprint"], "0aff");
+        assert_eq!(debug["relay_fingerprint"], "");
+    }
+}
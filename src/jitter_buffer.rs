@@ -0,0 +1,160 @@
+use crate::config::JitterBufferConfig;
+
+/// Outcome of replaying a latency sequence through a simulated jitter buffer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JitterReport {
+    pub frames: usize,
+    
+    /// Frames that arrived after their playout slot (audible gap)
+    pub underruns: usize,
+    
+    /// Frames dropped because the buffer was already full on arrival
+    pub overruns: usize,
+    
+    /// Mean time from send to playout across frames actually played
+    pub effective_playout_delay_ms: f64,
+}
+
+/// Replay per-frame one-way latencies (ms, in send order) through an adaptive
+/// jitter buffer. Frame `i` is sent at `i * frame_ms` and arrives at
+/// `i * frame_ms + latency[i]`; playout starts `initial_depth_ms` after the
+/// first arrival and advances one frame per `frame_ms`.
+pub fn simulate(latencies_ms: &[f64], config: &JitterBufferConfig) -> JitterReport {
+    let mut report = JitterReport {
+        frames: latencies_ms.len(),
+        ..Default::default()
+    };
+    
+    if latencies_ms.is_empty() {
+        return report;
+    }
+    
+    let frame_ms = config.frame_ms as f64;
+    let capacity = (config.max_depth_ms / config.frame_ms.max(1)).max(1) as usize;
+    
+    // Playout delay relative to send time; grows on underrun when adaptive
+    let mut delay = latencies_ms[0] + config.initial_depth_ms as f64;
+    let max_delay = latencies_ms[0] + config.max_depth_ms as f64;
+    let mut on_time_streak = 0u32;
+    let mut played_delay_total = 0.0;
+    let mut played = 0usize;
+    
+    for (i, &latency) in latencies_ms.iter().enumerate() {
+        let sent = i as f64 * frame_ms;
+        let arrival = sent + latency;
+        let playout = sent + delay;
+        
+        if arrival > playout {
+            report.underruns += 1;
+            on_time_streak = 0;
+            
+            if config.adaptive {
+                // Stretch playout so the next frame with similar latency lands in time
+                delay = (latency + frame_ms).min(max_delay).max(delay);
+            }
+            continue;
+        }
+        
+        // Frames queued ahead of this one: everything already arrived but not yet played
+        let queued = ((playout - arrival) / frame_ms).floor() as usize;
+        if queued >= capacity {
+            report.overruns += 1;
+            continue;
+        }
+        
+        played += 1;
+        played_delay_total += delay;
+        on_time_streak += 1;
+        
+        // Drain slowly after a sustained calm period so the buffer doesn't stay inflated
+        if config.adaptive && on_time_streak >= config.shrink_after_frames {
+            let floor = latency + config.initial_depth_ms as f64;
+            delay = (delay - frame_ms).max(floor);
+            on_time_streak = 0;
+        }
+    }
+    
+    if played > 0 {
+        report.effective_playout_delay_ms = played_delay_total / played as f64;
+    }
+    
+    report
+}
+
+/// RFC 3550 (6.4.1) interarrival jitter over a stream of one-way latencies:
+/// a running mean of how much each latency differs from the one before,
+/// smoothed with gain 1/16 so single outliers don't dominate
+#[derive(Debug, Clone, Default)]
+pub struct JitterEstimate {
+    last_latency_ms: Option<f64>,
+    jitter_ms: f64,
+}
+
+impl JitterEstimate {
+    /// Fold in the next latency (ms); None until there are two to compare
+    pub fn update(&mut self, latency_ms: f64) -> Option<f64> {
+        let last = self.last_latency_ms.replace(latency_ms)?;
+        self.jitter_ms += ((latency_ms - last).abs() - self.jitter_ms) / 16.0;
+        Some(self.jitter_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn config(adaptive: bool) -> JitterBufferConfig {
+        JitterBufferConfig {
+            frame_ms: 20,
+            initial_depth_ms: 40,
+            max_depth_ms: 200,
+            adaptive,
+            shrink_after_frames: 50,
+            window: 100,
+        }
+    }
+    
+    #[test]
+    fn steady_latency_plays_every_frame() {
+        let report = simulate(&[30.0; 50], &config(true));
+        assert_eq!((report.frames, report.underruns, report.overruns), (50, 0, 0));
+        assert_eq!(report.effective_playout_delay_ms, 70.0);
+    }
+    
+    #[test]
+    fn adaptive_buffer_grows_after_a_late_frame() {
+        // A 100ms spike, then latency settles 60ms higher than before
+        let mut latencies = vec![30.0; 10];
+        latencies.push(130.0);
+        latencies.extend([90.0; 10]);
+        
+        let fixed = simulate(&latencies, &config(false));
+        let adaptive = simulate(&latencies, &config(true));
+        assert_eq!(fixed.underruns, 11);
+        assert_eq!(adaptive.underruns, 1);
+        assert!(adaptive.effective_playout_delay_ms > fixed.effective_playout_delay_ms);
+    }
+    
+    #[test]
+    fn frames_beyond_max_depth_overrun() {
+        // Frames bunching up after a slow first one queue past the cap
+        let mut latencies = vec![200.0];
+        latencies.extend([0.0; 10]);
+        let report = simulate(&latencies, &JitterBufferConfig { max_depth_ms: 60, ..config(false) });
+        assert!(report.overruns > 0);
+    }
+    
+    #[test]
+    fn rfc3550_jitter_smooths_toward_the_latency_swing() {
+        let mut estimate = JitterEstimate::default();
+        assert_eq!(estimate.update(20.0), None);
+        assert_eq!(estimate.update(36.0), Some(1.0));
+        
+        // A constant 16ms swing converges on 16ms
+        let mut jitter = 0.0;
+        for i in 0..200 {
+            jitter = estimate.update(if i % 2 == 0 { 20.0 } else { 36.0 }).unwrap();
+        }
+        assert!((jitter - 16.0).abs() < 0.01, "{}", jitter);
+    }
+}
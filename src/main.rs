@@ -1,119 +1,751 @@
 mod config;
+mod detector_pool;
+mod fingerprint;
+mod http;
+mod jitter_buffer;
+mod metrics;
+mod openmetrics;
+mod parquet_export;
+mod pcap_replay;
+mod pxl;
+mod rate_limiter;
+mod rtp;
+mod sample_decode;
+mod self_test;
 mod signature_detector;
+mod signature_log;
+mod signature_store;
+mod statsd;
+mod toggles;
+mod tone_detector;
+mod units;
+mod validate;
+mod wav_input;
+mod windowing;
 
-use config::MeasurementConfig;
-use signature_detector::{SignatureDetector, SignatureEvent};
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use config::{CorrelationConfig, MeasurementConfig, SensorConfig};
+use metrics::{LatencyMetrics, MeasurementState, SensorMetrics};
+use rate_limiter::TokenBucket;
+use detector_pool::{DetectorPool, SignatureSink};
+use fingerprint::FingerprintMatcher;
+use http::MatchRecord;
+use jitter_buffer::JitterEstimate;
+use signature_detector::SignatureEvent;
+use signature_log::SignatureLog;
+use signature_store::SignatureStore;
+use statsd::StatsdSink;
+use toggles::MeasurementToggles;
+use tone_detector::ToneMatcher;
+use units::Millis;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch, Semaphore};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+    
+    let args: Vec<String> = std::env::args().collect();
+    
+    // Pre-flight mode: check a config without connecting to anything; exit code for CI
+    if let Some(pos) = args.iter().position(|a| a == "--validate") {
+        let path = args.get(pos + 1).map(String::as_str).unwrap_or("config.yaml");
+        let ok = validate_config(path);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    
+    // Offline mode: pair up signature logs collected from several pods
+    if let Some(pos) = args.iter().position(|a| a == "--match-logs") {
+        let paths: Vec<String> = args[pos + 1..].iter()
+            .take_while(|a| !a.starts_with("--"))
+            .cloned()
+            .collect();
+        if paths.is_empty() {
+            return Err("--match-logs requires one or more signature log paths".into());
+        }
+        
+        let matches = signature_log::match_logs(&paths)?;
+        println!("🗂️ {} matches across {} logs", matches.len(), paths.len());
+        for m in &matches {
+            println!("✅ {} {:016x}: {} -> {} latency {} (interval {})",
+                m.measurement, m.hash, m.source_pod, m.relay_pod, Millis(m.latency),
+                m.interval_id.as_deref().unwrap_or("unknown"));
+        }
+        return Ok(());
+    }
+    
     // Load config
     let config_yaml = std::fs::read_to_string("config.yaml")?;
-    let measurements: Vec<MeasurementConfig> = serde_yaml::from_str(&config_yaml)?;
+    let mut config: SensorConfig = serde_yaml::from_str(&config_yaml)?;
+    config.load_scripts()?;
+    
+    // Same checks as --validate: a zero rate or interval would panic or spin later
+    let report = config.validate();
+    print_report(&report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    
+    // Every metric this process exports lives in this one registry
+    let registry = prometheus::Registry::new();
+    let metrics = Arc::new(SensorMetrics::register(&registry)?);
+    
+    // Wiring check: synthetic signature through detector, broadcast and matcher
+    if args.iter().any(|a| a == "--self-test") {
+        match self_test::run(&config, &metrics).await {
+            Ok(latency) => println!("✅ Self-test passed: matched in {}", Millis(latency)),
+            Err(e) => {
+                println!("❌ Self-test failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    
+    // Offline mode: run a capture through the detector/matcher and exit
+    if let Some(pos) = args.iter().position(|a| a == "--replay") {
+        let path = args.get(pos + 1).ok_or("--replay requires a .pcap/.pcapng path")?;
+        let report = pcap_replay::replay(path, &config.pcap_replay, &config.measurements, config.hash_seed, &metrics)?;
+        
+        println!("📼 Replayed {} packets, {} signatures, {} matches",
+            report.packets, report.signatures, report.matches.len());
+        for m in &report.matches {
+            println!("✅ {} {:016x}: {} -> {} latency {}",
+                m.measurement, m.hash, m.source_flow, m.relay_flow, Millis(m.latency));
+        }
+        return Ok(());
+    }
+    
+    // Calibration mode: show where each measurement fires on a known recording
+    if let Some(pos) = args.iter().position(|a| a == "--wav") {
+        let path = args.get(pos + 1).ok_or("--wav requires a .wav path")?;
+        for measurement in config.measurements.iter().filter(|m| m.enabled) {
+            let hits = wav_input::run_wav(path, measurement, 20, config.hash_seed, &metrics)?;
+            println!("🎙️ {}: {} signatures", measurement.name, hits.len());
+            for hit in &hits {
+                println!("   {:>8}ms  {:016x} ({}ms window, confidence {:.2})",
+                    hit.offset_ms, hit.hash, hit.duration_ms, hit.confidence);
+            }
+        }
+        return Ok(());
+    }
+    
+    // Latency histograms labeled per measurement (fails fast on unknown labels)
+    let latency_metrics = Arc::new(LatencyMetrics::register(&config.measurements, &registry)?);
+    
+    // Optional StatsD sink, flushed periodically so quiet periods still ship
+    let statsd = match config.statsd.clone() {
+        Some(statsd_config) => {
+            let flush_every = Duration::from_millis(statsd_config.flush_interval_ms);
+            let sink = Arc::new(StatsdSink::new(statsd_config)?);
+            let flusher = sink.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(flush_every);
+                loop {
+                    ticker.tick().await;
+                    flusher.flush();
+                }
+            });
+            Some(sink)
+        }
+        None => None,
+    };
+    
+    // Optional local record of every detected signature, for offline matching
+    let signature_log = match &config.signature_log {
+        Some(log_config) => Some(Arc::new(SignatureLog::open(log_config)?)),
+        None => None,
+    };
     
     // Channel for broadcasting signatures between DaemonSet pods
-    let (sig_tx, _) = broadcast::channel::<SignatureEvent>(1000);
+    let (sig_tx, _) = broadcast::channel::<SignatureEvent>(config.signature_channel_capacity);
     
-    // Start detector task for each measurement
-    for measurement in measurements {
-        if measurement.enabled {
-            let sig_tx = sig_tx.clone();
-            tokio::spawn(run_measurement(measurement, sig_tx));
+    // Optional bounded path for this pod's own signatures: a slow matcher
+    // throttles the detectors instead of losing signatures to broadcast lag
+    let (local_tx, local_rx) = match config.local_signature_queue {
+        Some(depth) => {
+            let (tx, rx) = mpsc::channel::<SignatureEvent>(depth);
+            (Some(tx), Some(rx))
         }
+        None => (None, None),
+    };
+    
+    // Runtime on/off switches, flipped via POST /measurements/{name}/enabled
+    let toggles = MeasurementToggles::new(&config.measurements, &metrics);
+    
+    let sinks = MeasurementSinks {
+        sig_tx: sig_tx.clone(),
+        local_tx,
+        statsd: statsd.clone(),
+        toggles: toggles.clone(),
+        signature_log,
+        metrics: metrics.clone(),
+    };
+    
+    // Start detector task for each measurement, at most max_concurrent_measurements at a time
+    let slots = Arc::new(Semaphore::new(
+        config.max_concurrent_measurements.unwrap_or(Semaphore::MAX_PERMITS),
+    ));
+    for measurement in config.measurements.iter().filter(|m| m.enabled) {
+        let task = run_measurement(measurement.clone(), config.hash_seed, sinks.clone());
+        tokio::spawn(run_in_slot(measurement.name.clone(), task, slots.clone(), metrics.clone()));
     }
     
+    // Signatures awaiting a match, shared across all measurements
+    let store = Arc::new(SignatureStore::new());
+    tokio::spawn(run_signature_eviction(store.clone(), config.measurements.clone(), metrics.clone()));
+    
+    // Live feed of successful matches for WebSocket subscribers
+    let (match_tx, _) = broadcast::channel::<MatchRecord>(256);
+    
+    // Flipped on SIGTERM/Ctrl-C so exporters can finish their files
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let parquet_exporter = config.parquet_export.clone().map(|parquet_config| {
+        parquet_export::spawn_exporter(parquet_config, match_tx.subscribe(), shutdown_rx.clone())
+    });
+    
     // Start signature matcher (listens for broadcasts)
-    tokio::spawn(run_signature_matcher(sig_tx.subscribe()));
+    let listen_addr = config.listen_addr.clone();
+    let exemplars = config.metrics.exemplars.then(|| latency_metrics.exemplars());
+    let context = MatcherContext {
+        store,
+        match_tx: match_tx.clone(),
+        latency_metrics,
+        statsd,
+        toggles: toggles.clone(),
+        metrics,
+    };
+    tokio::spawn(run_signature_matcher(Arc::new(config), sig_tx.subscribe(), local_rx, context));
     
-    // Start metrics server
-    start_metrics_server().await?;
+    // Start metrics server, until the pod is told to stop
+    tokio::select! {
+        result = http::serve(&listen_addr, registry, match_tx, toggles, exemplars) => result?,
+        _ = shutdown_signal() => println!("🛑 Shutting down"),
+    }
+    
+    let _ = shutdown_tx.send(true);
+    if let Some(exporter) = parquet_exporter {
+        let _ = tokio::task::spawn_blocking(move || exporter.join()).await;
+    }
     
     Ok(())
 }
 
+/// Ctrl-C locally, SIGTERM when Kubernetes stops the pod
+async fn shutdown_signal() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// Load, parse and validate a config file, printing a summary; true if deployable
+fn validate_config(path: &str) -> bool {
+    let mut config: SensorConfig = match std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|yaml| serde_yaml::from_str(&yaml).map_err(|e| e.to_string()))
+    {
+        Ok(config) => config,
+        Err(e) => {
+            println!("❌ {}: {}", path, e);
+            return false;
+        }
+    };
+    
+    if let Err(e) = config.load_scripts() {
+        println!("❌ {}", e);
+        return false;
+    }
+    let mut report = config.validate();
+    // Label/metadata consistency is checked where the histograms are built
+    if let Err(e) = LatencyMetrics::register(&config.measurements, &prometheus::Registry::new()) {
+        report.errors.push(e.to_string());
+    }
+    
+    for m in config.measurements.iter().filter(|m| m.enabled) {
+        println!("📋 {}: {:?} VAD, {} id patterns, labels [{}]",
+            m.name, m.signature_rules.audio_criteria.vad_mode,
+            m.metadata_extraction.id_patterns.len(), m.metric_labels.join(", "));
+    }
+    print_report(&report);
+    
+    if report.is_ok() {
+        println!("✅ {} is valid", path);
+    }
+    report.is_ok()
+}
+
+fn print_report(report: &validate::ValidationReport) {
+    for warning in &report.warnings {
+        println!("⚠️ {}", warning);
+    }
+    for error in &report.errors {
+        println!("❌ {}", error);
+    }
+}
+
+/// Where every measurement's signatures and stats go, shared across measurements
+#[derive(Clone)]
+struct MeasurementSinks {
+    sig_tx: broadcast::Sender<SignatureEvent>,
+    /// This pod's own signatures, when local_signature_queue is set
+    local_tx: Option<mpsc::Sender<SignatureEvent>>,
+    statsd: Option<Arc<StatsdSink>>,
+    toggles: MeasurementToggles,
+    signature_log: Option<Arc<SignatureLog>>,
+    metrics: Arc<SensorMetrics>,
+}
+
 async fn run_measurement(
     config: MeasurementConfig,
-    sig_tx: broadcast::Sender<SignatureEvent>,
+    hash_seed: u64,
+    sinks: MeasurementSinks,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let MeasurementSinks { sig_tx, local_tx, statsd, toggles, signature_log, metrics } = sinks;
+    
     // Connect to local Pixie
-    let pixie_client = connect_to_pixie().await?;
+    let mut pixie_client = connect_to_pixie().await?;
     
-    // Create detector
-    let mut detector = SignatureDetector::new(config.clone());
+    // Detector workers, sharded by connection so per-stream state stays coherent
+    let measurement_name = config.name.clone();
+    let rate_limit = config.signature_rules.max_signatures_per_sec.map(|rate| {
+        let burst = config.signature_rules.signature_burst.unwrap_or(rate).max(1.0);
+        Mutex::new(TokenBucket::new(rate, burst))
+    });
+    let detected = metrics.signatures_detected.with_label_values(&[&measurement_name]);
+    let rate_limited = metrics.signatures_rate_limited.with_label_values(&[&measurement_name]);
+    let queue_depth = metrics.local_signature_queue_depth.clone();
+    let on_signature: SignatureSink = Arc::new(move |sig_event: SignatureEvent| {
+        detected.inc();
+        
+        // Offline matching wants every detection, including rate-limited ones
+        if let Some(log) = &signature_log {
+            if let Err(e) = log.append(&sig_event) {
+                tracing::warn!(measurement = %measurement_name, "signature log write failed: {}", e);
+            }
+        }
+        
+        if let Some(bucket) = &rate_limit {
+            if !bucket.lock().unwrap().try_acquire() {
+                rate_limited.inc();
+                return;
+            }
+        }
+        
+        println!("📡 Detected signature: {:?} with metadata: {:?}", 
+            sig_event.signature.hash,
+            sig_event.metadata.ids
+        );
+        
+        if let Some(statsd) = &statsd {
+            statsd.count("signatures.detected", 1, &[("measurement", &measurement_name)]);
+        }
+        
+        match &local_tx {
+            // Blocks this detector worker while the matcher is behind; the shard
+            // queue then fills and the packet loop waits in pool.submit()
+            Some(local_tx) => {
+                if local_tx.blocking_send(sig_event).is_ok() {
+                    queue_depth.set((local_tx.max_capacity() - local_tx.capacity()) as i64);
+                }
+            }
+            // Broadcast to all pods
+            None => {
+                let _ = sig_tx.send(sig_event);
+            }
+        }
+    });
+    let pool = DetectorPool::new(config.clone(), hash_seed, metrics.clone(), on_signature);
     
     // Stream packets from Pixie
+    let connected = metrics.stream_connected.with_label_values(&[&config.name]);
     let mut stream = pixie_client
-        .execute_script(config.signature_rules.stream_filter)
+        .execute_script(config.signature_rules.stream_filter.clone())
         .await?;
+    connected.set(1);
+    MeasurementState::Running.set(&metrics, &config.name);
+    
+    // No rows at all for a while usually means a broken filter, not silence;
+    // past the grace period the stream itself is presumed dead
+    let idle_after = Duration::from_secs(config.signature_rules.idle_warning_secs.max(1));
+    let grace = config.signature_rules.stream_grace_secs.map(|secs| Duration::from_secs(secs.max(1)));
+    let wake_every = grace.map_or(idle_after, |grace| grace.min(idle_after));
+    let mut rows = RowWatch::new(&config.name, idle_after, metrics.clone());
+    let traffic = metrics.traffic(&config.name, "source");
+    
+    loop {
+        // Wake periodically so a silent stream is still noticed
+        let batch = match tokio::time::timeout(wake_every, stream.next()).await {
+            Ok(batch) => match batch? {
+                Some(batch) => batch,
+                None => break,
+            },
+            Err(_) => Vec::new(),
+        };
+        
+        let now = Instant::now();
+        rows.batch(batch.len(), now);
+        
+        // Brief stalls just wait; one that outlasts the grace period reconnects
+        if grace.is_some_and(|grace| rows.quiet_for(now) >= grace) {
+            connected.set(0);
+            tracing::warn!(
+                measurement = %config.name,
+                "no rows for {}; reconnecting to Pixie",
+                Millis(rows.quiet_for(now))
+            );
+            pixie_client = connect_to_pixie().await?;
+            stream = pixie_client
+                .execute_script(config.signature_rules.stream_filter.clone())
+                .await?;
+            connected.set(1);
+            rows.restart(Instant::now());
+            continue;
+        }
+        
+        // Switched off at runtime: keep the stream (and idle tracking) alive, skip detection
+        if !toggles.is_enabled(&config.name) {
+            continue;
+        }
         
-    while let Some(batch) = stream.next().await? {
         for row in batch {
             let payload = row.get_bytes("payload");
+            traffic.record(payload);
+            let upid = row.get_string("upid");
+            let pod_name = row.get_string("pod_name");
+            // Pixie time_: wall-clock nanos, the clock the matcher compares against
+            let captured_at_ns = row.get_timestamp("timestamp");
             
             // Process packet - might generate signature
-            if let Some(sig_event) = detector.process_packet(payload) {
-                println\!("📡 Detected signature: {:?} with metadata: {:?}", 
-                    sig_event.signature.hash,
-                    sig_event.metadata.ids
-                );
-                
-                // Broadcast to all pods
-                let _ = sig_tx.send(sig_event);
-            }
+            // Fails once a detector hit missing_metadata_policy: error
+            pool.submit(&upid, payload.to_vec(), captured_at_ns, &pod_name).await?;
         }
     }
+    connected.set(0);
+    MeasurementState::Idle.set(&metrics, &config.name);
     
     Ok(())
 }
 
+/// Rows from one measurement's stream_filter: counts them and flags the
+/// measurement idle once none have arrived for `idle_after`
+struct RowWatch {
+    name: String,
+    idle_after: Duration,
+    last_row: Instant,
+    rows: prometheus::IntCounter,
+    idle: prometheus::IntGauge,
+    metrics: Arc<SensorMetrics>,
+}
+
+impl RowWatch {
+    fn new(name: &str, idle_after: Duration, metrics: Arc<SensorMetrics>) -> Self {
+        Self {
+            name: name.to_string(),
+            idle_after,
+            last_row: Instant::now(),
+            rows: metrics.stream_rows.with_label_values(&[name]),
+            idle: metrics.stream_idle.with_label_values(&[name]),
+            metrics,
+        }
+    }
+    
+    /// Account for a batch of `rows` (possibly none) received at `now`
+    fn batch(&mut self, rows: usize, now: Instant) {
+        if rows > 0 {
+            self.last_row = now;
+            self.rows.inc_by(rows as u64);
+            if self.idle.get() == 1 {
+                println!("📶 {} is receiving rows again", self.name);
+                self.idle.set(0);
+                MeasurementState::Running.set(&self.metrics, &self.name);
+            }
+        } else if self.quiet_for(now) >= self.idle_after && self.idle.get() == 0 {
+            tracing::warn!(
+                measurement = %self.name,
+                "stream_filter returned no rows for {}; check its table and port",
+                Millis(self.idle_after)
+            );
+            self.idle.set(1);
+            MeasurementState::Idle.set(&self.metrics, &self.name);
+        }
+    }
+    
+    fn quiet_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_row)
+    }
+    
+    /// Start the quiet period over, e.g. on a fresh connection
+    fn restart(&mut self, now: Instant) {
+        self.last_row = now;
+    }
+}
+
+/// Handles the matcher shares with the rest of the sensor
+struct MatcherContext {
+    /// Signatures awaiting a match, also swept by the eviction task
+    store: Arc<SignatureStore>,
+    match_tx: broadcast::Sender<MatchRecord>,
+    latency_metrics: Arc<LatencyMetrics>,
+    statsd: Option<Arc<StatsdSink>>,
+    toggles: MeasurementToggles,
+    metrics: Arc<SensorMetrics>,
+}
+
 async fn run_signature_matcher(
-    mut sig_rx: broadcast::Receiver<SignatureEvent>
+    config: Arc<SensorConfig>,
+    mut sig_rx: broadcast::Receiver<SignatureEvent>,
+    local_rx: Option<mpsc::Receiver<SignatureEvent>>,
+    context: MatcherContext,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Track active signatures we're looking for
-    let active_signatures = Arc::new(dashmap::DashMap::new());
+    let MatcherContext { store, match_tx, latency_metrics, statsd, toggles, metrics } = context;
+    let hash_seed = config.hash_seed;
+    let metrics_config = &config.metrics;
+    let jitter_config = &config.jitter_buffer;
+    
+    let min_durations: Arc<HashMap<String, u32>> = Arc::new(config.measurements.iter()
+        .map(|m| (m.name.clone(), m.correlation.min_signature_duration_ms))
+        .collect());
+    
+    // Drain this pod's own signatures from the bounded queue, if enabled
+    if let Some(mut local_rx) = local_rx {
+        let active_signatures = store.clone();
+        let min_durations = min_durations.clone();
+        let toggles = toggles.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            while let Some(sig) = local_rx.recv().await {
+                metrics.local_signature_queue_depth.set(local_rx.len() as i64);
+                if !toggles.is_enabled(&sig.measurement_name) || too_short(&min_durations, &sig, &metrics) {
+                    continue;
+                }
+                println!("🔍 Searching for signature: {:?}", sig.signature.hash);
+                active_signatures.insert(sig);
+            }
+        });
+    }
     
     // Listen for signature broadcasts
+    let active_signatures = store.clone();
+    let sig_metrics = metrics.clone();
     tokio::spawn(async move {
-        while let Ok(sig) = sig_rx.recv().await {
-            println\!("🔍 Searching for signature: {:?}", sig.signature.hash);
-            active_signatures.insert(sig.signature.hash, sig);
+        loop {
+            match sig_rx.recv().await {
+                Ok(sig) if sig.signature.seed != hash_seed => {
+                    // Another deployment's hash space; its values mean nothing here
+                    println!("🚫 Ignoring signature {:?} with foreign seed", sig.signature.hash);
+                }
+                Ok(sig) if !toggles.is_enabled(&sig.measurement_name) => {}
+                Ok(sig) if too_short(&min_durations, &sig, &sig_metrics) => {}
+                Ok(sig) => {
+                    println!("🔍 Searching for signature: {:?}", sig.signature.hash);
+                    active_signatures.insert(sig);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // Burst outran us; those signatures are gone but later ones still count
+                    println!("⚠️ Signature receiver lagged, dropped {} signatures", skipped);
+                    sig_metrics.signatures_dropped.inc_by(skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
         }
     });
     
-    // Query local Pixie for all audio traffic
+    // Query local Pixie for all audio traffic, re-issued every refresh_secs if set
     let pixie_client = connect_to_pixie().await?;
-    let mut stream = pixie_client
-        .execute_script(r#"
-            df = px.DataFrame(table='socket_data', start_time='10s')
-            df = df[df.local_port == 15000 or df.remote_port == 15000]
-            df[['timestamp', 'pod_name', 'upid', 'payload']]
-        "#)
-        .await?;
+    let query = pxl::matcher_query(&config.matcher_query)?;
+    let refresh = config.matcher_query.refresh_secs.map(Duration::from_secs);
+    let mut stream = pixie_client.execute_script(query.clone()).await?;
+    let mut refresh_at = refresh.map(|every| tokio::time::Instant::now() + every);
+    
+    // Latency history and jitter per correlation group (or measurement, ungrouped)
+    let mut groups: HashMap<String, GroupState> = HashMap::new();
+    let correlations: HashMap<String, CorrelationConfig> = config.measurements.iter()
+        .map(|m| (m.name.clone(), m.correlation.clone()))
+        .collect();
     
-    while let Some(batch) = stream.next().await? {
+    // Content measurements fingerprint the relayed streams the way their
+    // detectors did; tone-mode ones match on onsets
+    let mut fingerprint_matcher = FingerprintMatcher::new(&config.measurements);
+    let mut tone_matcher = ToneMatcher::new(&config.measurements);
+    
+    let mut recent_matches = RecentMatches::new(Duration::from_millis(config.measurements.iter()
+        .map(|m| m.correlation.match_dedup_ms)
+        .max()
+        .unwrap_or(0)));
+    
+    // Relayed traffic isn't attributed to a measurement
+    let traffic = metrics.traffic("", "relay");
+    
+    loop {
+        let batch = match refresh_at {
+            Some(at) => tokio::time::timeout_at(at, stream.next()).await.unwrap_or(Ok(None))?,
+            None => stream.next().await?,
+        };
+        let Some(batch) = batch else {
+            // Window over (or stream ended): query again. Rows in the overlap
+            // with the previous window can't double-count, since a matched
+            // signature has already left the store.
+            let Some(every) = refresh else { break };
+            stream = pixie_client.execute_script(query.clone()).await?;
+            refresh_at = Some(tokio::time::Instant::now() + every);
+            continue;
+        };
+        
+        let now = Instant::now();
+        recent_matches.prune(now);
+        groups.retain(|_, state| !state.is_expired(now));
+        fingerprint_matcher.prune_idle();
+        tone_matcher.prune_idle();
+        
         for row in batch {
             let payload = row.get_bytes("payload");
+            traffic.record(payload);
             let pod_name = row.get_string("pod_name");
-            let timestamp = row.get_timestamp("timestamp");
+            // Pixie time_: wall-clock nanos, same domain as the signature's timestamps
+            let timestamp_ns = row.get_timestamp("timestamp");
+            
+            let upid = row.get_string("upid");
             
-            // Quick signature check (simplified - real would reassemble streams)
-            let hash = xxhash_rust::xxh3::xxh3_64(payload);
+            // Encrypted streams' timing signatures are the packet's own hash, since
+            // relays forward ciphertext as-is. Matcher traffic isn't attributed to a
+            // measurement, so search every namespace.
+            let hash = xxhash_rust::xxh3::xxh3_64_with_seed(payload, hash_seed);
+            let mut candidates: Vec<(SignatureEvent, Vec<u8>)> = store.take_any(hash, None)
+                .map(|sig| (sig, payload.to_vec()))
+                .into_iter()
+                .collect();
             
-            if let Some((_, original_sig)) = active_signatures.remove(&hash) {
-                let latency = timestamp - original_sig.timestamp;
+            // Content signatures hash decoded audio, never the payload bytes: the
+            // relayed stream has to be fingerprinted the same way to find them
+            if !fingerprint_matcher.is_empty() {
+                candidates.extend(fingerprint_matcher.observe(&upid, payload, &store, hash_seed));
+            }
+            
+            // Tone measurements correlate on the tone's onset in the relayed stream
+            if !tone_matcher.is_empty() {
+                candidates.extend(tone_matcher.observe(&upid, payload, &store, hash_seed)
+                    .into_iter()
+                    .map(|sig| (sig, Vec::new())));
+            }
+            
+            for (original_sig, relay_fingerprint) in candidates {
+                let interval_id = original_sig.metadata.ids.get("interval_id")
+                    .cloned()
+                    .unwrap_or_else(|| "unknown".to_string());
+                
+                // End-to-end from audio capture: the only figure that decides negativity
+                let Some(latency) = original_sig.latency_to(timestamp_ns) else {
+                    tracing::warn!(
+                        measurement = %original_sig.measurement_name,
+                        interval_id = %interval_id,
+                        source_pod = ?original_sig.source_pod,
+                        relay_pod = %pod_name,
+                        "negative latency: match observed before its signature; excluded from stats"
+                    );
+                    metrics.negative_latency
+                        .with_label_values(&[&original_sig.measurement_name])
+                        .inc();
+                    continue;
+                };
+                
+                // The same event matched again shortly after: keep the first latency
+                let dedup = correlations.get(&original_sig.measurement_name)
+                    .map_or(0, |correlation| correlation.match_dedup_ms);
+                if dedup > 0 && recent_matches.is_repeat(&original_sig, Duration::from_millis(dedup), Instant::now()) {
+                    metrics.duplicate_matches
+                        .with_label_values(&[&original_sig.measurement_name])
+                        .inc();
+                    continue;
+                }
                 
-                println\!("✅ Match found\! Latency: {:?}ms from pod: {}", 
-                    latency.as_millis(), pod_name);
+                // Network-only excludes our own detection time; detection can end
+                // after the relay capture, which drops this figure but not the match
+                let network_latency = timestamp_ns.checked_sub(original_sig.generated_at_ns).map(Duration::from_nanos);
                 
-                // Record metrics
-                LATENCY_HISTOGRAM
-                    .with_label_values(&[
-                        &original_sig.measurement_name,
-                        &original_sig.metadata.ids.get("interval_id").unwrap_or(&"unknown".to_string()),
-                        &pod_name
-                    ])
-                    .observe(latency.as_secs_f64());
+                println!("✅ Match found! Latency: {} (network {}) from pod: {} to pod: {}", 
+                    Millis(latency), network_latency.map_or_else(|| "n/a".to_string(), |d| Millis(d).to_string()),
+                    original_sig.source_pod.as_deref().unwrap_or("unknown"), pod_name);
+                
+                let group = correlations.get(&original_sig.measurement_name)
+                    .and_then(|correlation| original_sig.metadata.grouping_key(correlation));
+                if group.is_none() {
+                    metrics.grouping_key_missing
+                        .with_label_values(&[&original_sig.measurement_name])
+                        .inc();
+                }
+                
+                // Record metrics with the measurement's own label set;
+                // high-cardinality dimensions are blanked or bucketed per config
+                let labels = latency_metrics.labels(&original_sig.measurement_name, metrics_config, |name| {
+                    match_label(name, &original_sig, &pod_name)
+                });
+                let exemplar = metrics_config.exemplars.then_some(("interval_id", interval_id.as_str()));
+                latency_metrics.observe(&labels, latency, network_latency, exemplar);
+                
+                // Exported per measurement: groups are as unbounded as interval_id
+                let key = group.clone().unwrap_or_else(|| original_sig.measurement_name.clone());
+                let ttl = correlations.get(&original_sig.measurement_name)
+                    .map_or(Duration::from_secs(300), |correlation| Duration::from_secs(correlation.signature_ttl_seconds));
+                let state = groups.entry(key).or_insert_with(|| GroupState::new(ttl));
+                state.last_match = Instant::now();
+                if let Some(jitter_ms) = state.jitter.update(latency.as_secs_f64() * 1000.0) {
+                    metrics.latency_jitter_seconds
+                        .with_label_values(&[&original_sig.measurement_name])
+                        .observe(jitter_ms / 1000.0);
+                }
+                
+                if let Some(statsd) = &statsd {
+                    let tags: Vec<(&str, &str)> = labels.iter()
+                        .filter(|(_, value)| !value.is_empty())
+                        .map(|(name, value)| (name.as_str(), value.as_str()))
+                        .collect();
+                    statsd.timing("latency", latency.as_secs_f64() * 1000.0, &tags);
+                    if let Some(network_latency) = network_latency {
+                        statsd.timing("latency.network", network_latency.as_secs_f64() * 1000.0, &tags);
+                    }
+                    statsd.count("signatures.matched", 1, &tags);
+                }
+                
+                let mut record = MatchRecord {
+                    measurement: original_sig.measurement_name.clone(),
+                    interval_id: interval_id.clone(),
+                    latency_ms: latency.as_secs_f64() * 1000.0,
+                    source_pod: original_sig.source_pod.clone(),
+                    relay_pod: pod_name.clone(),
+                    matched_at_ms: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_millis() as u64)
+                        .unwrap_or(0),
+                    source_fingerprint: None,
+                    relay_fingerprint: None,
+                };
+                if config.debug_fingerprints {
+                    record = record.with_fingerprints(&original_sig.signature.fingerprint, &relay_fingerprint);
+                    println!("🧬 Fingerprints for {:016x}: source {} relay {}",
+                        original_sig.signature.hash,
+                        record.source_fingerprint.as_deref().unwrap_or(""),
+                        record.relay_fingerprint.as_deref().unwrap_or(""));
+                }
+                
+                // Nobody subscribed is the common case; send only fails then
+                let _ = match_tx.send(record);
+                
+                if let (Some(jitter_config), Some(group)) = (jitter_config, group) {
+                    let history = &mut state.latencies;
+                    history.push(latency.as_secs_f64() * 1000.0);
+                    
+                    if history.len() >= jitter_config.window {
+                        let report = jitter_buffer::simulate(history, jitter_config);
+                        println!("🎚️ Jitter buffer for {}: {} underruns, {} overruns, {:.1}ms playout delay",
+                            group, report.underruns, report.overruns,
+                            report.effective_playout_delay_ms);
+                        history.clear();
+                    }
+                }
             }
         }
     }
@@ -121,31 +753,280 @@ async fn run_signature_matcher(
     Ok(())
 }
 
-async fn connect_to_pixie() -> Result<PixieClient, Box<dyn std::error::Error>> {
-    // TODO: Implement actual Pixie gRPC connection
-    unimplemented\!()
+/// Matcher state for one correlation group. Dropped once the group has gone
+/// quiet for its measurement's signature TTL: by then no signature of it is
+/// left to match, so the call or stream it tracked is over.
+struct GroupState {
+    /// Recent latencies, replayed through the jitter buffer model
+    latencies: Vec<f64>,
+    /// Running RFC 3550 jitter
+    jitter: JitterEstimate,
+    last_match: Instant,
+    ttl: Duration,
 }
 
-async fn start_metrics_server() -> Result<(), Box<dyn std::error::Error>> {
-    // Prometheus metrics endpoint
-    // TODO: Implement
-    Ok(())
+impl GroupState {
+    fn new(ttl: Duration) -> Self {
+        Self { latencies: Vec::new(), jitter: JitterEstimate::default(), last_match: Instant::now(), ttl }
+    }
+    
+    fn is_expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_match) >= self.ttl
+    }
+}
+
+/// When each signature, by (measurement, source pod, capture time), last
+/// matched, for match_dedup_ms; the hash alone recurs across distinct events
+struct RecentMatches {
+    matched_at: HashMap<(String, Option<String>, u64), Instant>,
+    /// Longest dedup window of any measurement
+    horizon: Duration,
+}
+
+impl RecentMatches {
+    fn new(horizon: Duration) -> Self {
+        Self { matched_at: HashMap::new(), horizon }
+    }
+    
+    /// Whether `sig` already matched within `window` of `now`; if not, this
+    /// match starts a new window
+    fn is_repeat(&mut self, sig: &SignatureEvent, window: Duration, now: Instant) -> bool {
+        let key = (sig.measurement_name.clone(), sig.source_pod.clone(), sig.captured_at_ns);
+        if self.matched_at.get(&key).is_some_and(|at| now.saturating_duration_since(*at) < window) {
+            return true;
+        }
+        self.matched_at.insert(key, now);
+        false
+    }
+    
+    /// Forget matches too old to be repeated within any window
+    fn prune(&mut self, now: Instant) {
+        let horizon = self.horizon;
+        self.matched_at.retain(|_, at| now.saturating_duration_since(*at) < horizon);
+    }
+}
+
+/// Raw value of metric label `name` for a match of `sig` on `relay_pod`:
+/// the pods at either end of the hop, or an id from the signature's metadata
+fn match_label(name: &str, sig: &SignatureEvent, relay_pod: &str) -> Option<String> {
+    match name {
+        "source_pod" => sig.source_pod.clone(),
+        "relay_pod" | "pod" => Some(relay_pod.to_string()),
+        id_type => sig.metadata.ids.get(id_type).cloned(),
+    }
+}
+
+/// Run a measurement task once one of `slots` is free, tracked in the
+/// queued/active gauges; a task that fails marks its measurement errored
+async fn run_in_slot<E: std::fmt::Display>(
+    name: String,
+    task: impl std::future::Future<Output = Result<(), E>>,
+    slots: Arc<Semaphore>,
+    metrics: Arc<SensorMetrics>,
+) {
+    metrics.measurements_queued.inc();
+    let Ok(_slot) = slots.acquire_owned().await else { return };
+    metrics.measurements_queued.dec();
+    metrics.measurements_active.inc();
+    let result = task.await;
+    metrics.measurements_active.dec();
+    if let Err(e) = result {
+        MeasurementState::Errored.set(&metrics, &name);
+        tracing::error!(measurement = %name, "measurement stopped: {}", e);
+    }
+}
+
+/// Reject signatures below their measurement's duration floor (counted);
+/// tone onsets carry no duration and always pass
+fn too_short(min_durations: &HashMap<String, u32>, sig: &SignatureEvent, metrics: &SensorMetrics) -> bool {
+    let floor = min_durations.get(&sig.measurement_name).copied().unwrap_or(0);
+    if sig.signature.onset_sample.is_some() || sig.signature.duration_ms >= floor {
+        return false;
+    }
+    
+    println!("🚫 Ignoring {}ms signature {:?} (floor {}ms)", sig.signature.duration_ms, sig.signature.hash, floor);
+    metrics.signatures_too_short.with_label_values(&[&sig.measurement_name]).inc();
+    true
+}
+
+/// Expire signatures past their measurement's TTL; each one is a broadcast
+/// that never found its audio downstream
+async fn run_signature_eviction(
+    store: Arc<SignatureStore>,
+    measurements: Vec<MeasurementConfig>,
+    metrics: Arc<SensorMetrics>,
+) {
+    let ttls: HashMap<String, Duration> = measurements.iter()
+        .map(|m| (m.name.clone(), Duration::from_secs(m.correlation.signature_ttl_seconds)))
+        .collect();
+    let default_ttl = Duration::from_secs(300);
+    
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        
+        for sig in store.evict_expired(|m| ttls.get(m).copied().unwrap_or(default_ttl)) {
+            metrics.signatures_unmatched
+                .with_label_values(&[&sig.measurement_name])
+                .inc();
+        }
+    }
+}
+
+async fn connect_to_pixie() -> Result<PixieClient, Box<dyn std::error::Error>> {
+    // TODO: Implement actual Pixie gRPC connection
+    unimplemented!()
 }
 
 // Placeholder types
 struct PixieClient;
 impl PixieClient {
     async fn execute_script(&self, script: String) -> Result<StreamHandle, Box<dyn std::error::Error>> {
-        unimplemented\!()
+        unimplemented!()
     }
 }
 struct StreamHandle;
 
-lazy_static::lazy_static\! {
-    static ref LATENCY_HISTOGRAM: prometheus::HistogramVec = prometheus::register_histogram_vec\!(
-        "audio_latency_seconds",
-        "Audio processing latency",
-        &["measurement", "interval_id", "pod"]
-    ).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::test_metrics;
+    use signature_detector::{AudioSignature, PacketMetadata};
+    
+    fn signature(measurement: &str, duration_ms: u32, onset_sample: Option<u64>) -> SignatureEvent {
+        SignatureEvent {
+            signature: AudioSignature {
+                hash: 1,
+                duration_ms,
+                seed: 0,
+                onset_sample,
+                confidence: 1.0,
+                fingerprint: Vec::new(),
+            },
+            metadata: PacketMetadata::default(),
+            captured_at_ns: 0,
+            generated_at_ns: 0,
+            measurement_name: measurement.to_string(),
+            source_pod: None,
+        }
+    }
+    
+    #[test]
+    fn signatures_below_their_floor_are_counted_and_dropped() {
+        let metrics = test_metrics();
+        let floors = HashMap::from([("calls".to_string(), 300)]);
+        
+        assert!(too_short(&floors, &signature("calls", 200, None), &metrics));
+        assert!(!too_short(&floors, &signature("calls", 300, None), &metrics));
+        // Tone onsets have no duration; unknown measurements have no floor
+        assert!(!too_short(&floors, &signature("calls", 0, Some(1600)), &metrics));
+        assert!(!too_short(&floors, &signature("other", 0, None), &metrics));
+        
+        assert_eq!(metrics.signatures_too_short.with_label_values(&["calls"]).get(), 1);
+    }
+    
+    #[tokio::test]
+    async fn measurements_beyond_the_limit_wait_for_a_slot() {
+        let metrics = Arc::new(test_metrics());
+        let slots = Arc::new(Semaphore::new(1));
+        let (release, released) = watch::channel(false);
+        
+        let tasks: Vec<_> = (0..3)
+            .map(|i| {
+                let mut released = released.clone();
+                let task = async move { released.wait_for(|done| *done).await.map(|_| ()) };
+                tokio::spawn(run_in_slot(format!("m{}", i), task, slots.clone(), metrics.clone()))
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!((metrics.measurements_active.get(), metrics.measurements_queued.get()), (1, 2));
+        
+        release.send(true).unwrap();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!((metrics.measurements_active.get(), metrics.measurements_queued.get()), (0, 0));
+    }
+    
+    #[test]
+    fn match_labels_name_both_ends_of_the_hop() {
+        let mut sig = signature("calls", 200, None);
+        sig.source_pod = Some("source-0".to_string());
+        sig.metadata.ids.insert("interval_id".to_string(), "42".to_string());
+        
+        let label = |name| match_label(name, &sig, "relay-1");
+        assert_eq!(label("source_pod").as_deref(), Some("source-0"));
+        assert_eq!(label("relay_pod").as_deref(), Some("relay-1"));
+        assert_eq!(label("pod").as_deref(), Some("relay-1"));
+        assert_eq!(label("interval_id").as_deref(), Some("42"));
+        assert_eq!(label("call_id"), None);
+    }
+    
+    #[test]
+    fn a_quiet_stream_filter_goes_idle_until_rows_return() {
+        let metrics = Arc::new(test_metrics());
+        let mut rows = RowWatch::new("m", Duration::from_secs(5), metrics.clone());
+        let start = Instant::now();
+        let state = |label: &str| metrics.measurement_state.with_label_values(&["m", label]).get();
+        MeasurementState::Running.set(&metrics, "m");
+        
+        rows.batch(0, start + Duration::from_secs(1));
+        assert_eq!(rows.idle.get(), 0);
+        rows.batch(0, start + Duration::from_secs(6));
+        assert_eq!((rows.idle.get(), state("idle")), (1, 1));
+        
+        rows.batch(3, start + Duration::from_secs(7));
+        assert_eq!((rows.idle.get(), state("running")), (0, 1));
+        assert_eq!(rows.rows.get(), 3);
+        assert_eq!(rows.quiet_for(start + Duration::from_secs(9)), Duration::from_secs(2));
+    }
+    
+    #[test]
+    fn repeats_are_the_same_capture_within_the_window() {
+        let window = Duration::from_millis(500);
+        let mut recent = RecentMatches::new(window);
+        let start = Instant::now();
+        let sig = signature("calls", 200, None);
+        
+        assert!(!recent.is_repeat(&sig, window, start));
+        assert!(recent.is_repeat(&sig, window, start + Duration::from_millis(100)));
+        // Same hash, different moment: a distinct event
+        let mut later = sig.clone();
+        later.captured_at_ns += 1;
+        assert!(!recent.is_repeat(&later, window, start + Duration::from_millis(100)));
+        assert!(!recent.is_repeat(&sig, window, start + Duration::from_millis(600)));
+        
+        recent.prune(start + Duration::from_secs(2));
+        assert!(recent.matched_at.is_empty());
+    }
+    
+    #[test]
+    fn groups_expire_a_ttl_after_their_last_match() {
+        let mut groups: HashMap<String, GroupState> = HashMap::new();
+        groups.insert("short".to_string(), GroupState::new(Duration::from_secs(10)));
+        groups.insert("long".to_string(), GroupState::new(Duration::from_secs(60)));
+        let start = groups["short"].last_match;
+        groups.get_mut("long").unwrap().jitter.update(30.0);
+        
+        groups.retain(|_, state| !state.is_expired(start + Duration::from_secs(10)));
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["long"]);
+        
+        // A match keeps the group (and its jitter history) alive
+        let state = groups.get_mut("long").unwrap();
+        state.last_match = start + Duration::from_secs(50);
+        assert!(!state.is_expired(start + Duration::from_secs(100)));
+        assert_eq!(state.jitter.update(34.0), Some(0.25));
+    }
+    
+    #[tokio::test]
+    async fn a_failed_measurement_is_reported_errored() {
+        let metrics = Arc::new(test_metrics());
+        MeasurementState::Running.set(&metrics, "m");
+        let slots = Arc::new(Semaphore::new(1));
+        
+        run_in_slot("m".to_string(), async { Err("pixie went away") }, slots, metrics.clone()).await;
+        let state = |label: &str| metrics.measurement_state.with_label_values(&["m", label]).get();
+        assert_eq!((state("running"), state("idle"), state("errored")), (0, 0, 1));
+        assert_eq!(metrics.measurements_active.get(), 0);
+    }
 }
-EOF < /dev/null
\ No newline at end of file
@@ -0,0 +1,475 @@
+use crate::config::{MeasurementConfig, MetricsConfig};
+use crate::openmetrics::Exemplars;
+use prometheus::core::Collector;
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+impl MetricsConfig {
+    /// Value to export for a label: the raw value, a bounded hash bucket, or
+    /// blank when the dimension is filtered out (an empty value is equivalent
+    /// to an absent label in Prometheus, so the series collapses)
+    pub fn label_value(&self, name: &str, raw: &str) -> String {
+        let allowed = self.allow_labels.as_ref().is_none_or(|allow| allow.iter().any(|l| l == name))
+            && !self.deny_labels.iter().any(|l| l == name);
+        if !allowed {
+            return String::new();
+        }
+        
+        if self.hash_labels.iter().any(|l| l == name) {
+            let bucket = xxhash_rust::xxh3::xxh3_64(raw.as_bytes()) % self.hash_buckets.max(1);
+            return format!("h{:03}", bucket);
+        }
+        
+        raw.to_string()
+    }
+}
+
+/// Latency histograms labeled per measurement. Prometheus needs one label-name
+/// set per metric, so the histograms carry the union of every measurement's
+/// `metric_labels`; each measurement fills its own and leaves the rest blank.
+pub struct LatencyMetrics {
+    label_names: Vec<String>,
+    label_sets: HashMap<String, Vec<String>>,
+    end_to_end: HistogramVec,
+    network: HistogramVec,
+    exemplars: Arc<Exemplars>,
+}
+
+impl LatencyMetrics {
+    /// Validate each measurement's labels against the metadata it can produce
+    /// and register the histograms in `registry`. Separate registries keep
+    /// independent instances (tests, validation) from colliding.
+    pub fn register(measurements: &[MeasurementConfig], registry: &Registry) -> prometheus::Result<Self> {
+        let mut label_names = vec!["measurement".to_string()];
+        let mut label_sets = HashMap::new();
+        
+        for measurement in measurements {
+            let available: Vec<&str> = ["source_pod", "relay_pod", "pod", "interval_id"].into_iter()
+                .chain(measurement.metadata_extraction.id_patterns.iter().map(|p| p.id_type.as_str()))
+                .collect();
+            
+            for label in &measurement.metric_labels {
+                if !available.contains(&label.as_str()) {
+                    return Err(prometheus::Error::Msg(format!(
+                        "measurement '{}': metric label '{}' is not produced by its metadata (available: {})",
+                        measurement.name, label, available.join(", ")
+                    )));
+                }
+                if !label_names.contains(label) {
+                    label_names.push(label.clone());
+                }
+            }
+            label_sets.insert(measurement.name.clone(), measurement.metric_labels.clone());
+        }
+        
+        let names: Vec<&str> = label_names.iter().map(String::as_str).collect();
+        let end_to_end = HistogramVec::new(
+            HistogramOpts::new("audio_latency_seconds", "Audio processing latency, from audio capture to match"),
+            &names,
+        )?;
+        let network = HistogramVec::new(
+            HistogramOpts::new(
+                "audio_network_latency_seconds",
+                "Latency from signature generation to match, excluding detection processing",
+            ),
+            &names,
+        )?;
+        registry.register(Box::new(end_to_end.clone()))?;
+        registry.register(Box::new(network.clone()))?;
+        
+        Ok(Self { label_names, label_sets, end_to_end, network, exemplars: Arc::default() })
+    }
+    
+    /// (label, value) pairs for one match, in registration order. Labels the
+    /// measurement didn't ask for are blank; `lookup` resolves the rest.
+    pub fn labels(
+        &self,
+        measurement: &str,
+        filter: &MetricsConfig,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Vec<(String, String)> {
+        let wanted = self.label_sets.get(measurement);
+        
+        self.label_names.iter()
+            .map(|name| {
+                let value = if name == "measurement" {
+                    filter.label_value(name, measurement)
+                } else if wanted.is_some_and(|w| w.contains(name)) {
+                    let raw = lookup(name).unwrap_or_else(|| "unknown".to_string());
+                    filter.label_value(name, &raw)
+                } else {
+                    String::new()
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+    
+    /// `exemplar` (label, value), e.g. the raw interval_id, is kept against
+    /// the bucket each latency lands in, for OpenMetrics exposition
+    pub fn observe(
+        &self,
+        labels: &[(String, String)],
+        latency: Duration,
+        network_latency: Option<Duration>,
+        exemplar: Option<(&str, &str)>,
+    ) {
+        let values: Vec<&str> = labels.iter().map(|(_, v)| v.as_str()).collect();
+        self.end_to_end.with_label_values(&values).observe(latency.as_secs_f64());
+        if let Some(network_latency) = network_latency {
+            self.network.with_label_values(&values).observe(network_latency.as_secs_f64());
+        }
+        
+        if let Some(exemplar) = exemplar {
+            let buckets = prometheus::DEFAULT_BUCKETS;
+            self.exemplars.record("audio_latency_seconds", labels, buckets, latency.as_secs_f64(), exemplar);
+            if let Some(network_latency) = network_latency {
+                self.exemplars.record("audio_network_latency_seconds", labels, buckets, network_latency.as_secs_f64(), exemplar);
+            }
+        }
+    }
+    
+    /// Exemplars recorded by `observe`, for the metrics endpoint
+    pub fn exemplars(&self) -> Arc<Exemplars> {
+        self.exemplars.clone()
+    }
+}
+
+/// Every sensor counter and gauge, registered in a registry the caller owns
+/// (like LatencyMetrics) so several instances can live in one process
+pub struct SensorMetrics {
+    pub signatures_dropped: IntCounter,
+    pub negative_latency: IntCounterVec,
+    pub duplicate_matches: IntCounterVec,
+    pub signatures_missing_metadata: IntCounterVec,
+    pub packets_sampled: IntCounterVec,
+    pub stream_rows: IntCounterVec,
+    pub packets_processed: IntCounterVec,
+    pub bytes_processed: IntCounterVec,
+    pub stream_idle: IntGaugeVec,
+    pub packet_processing_seconds: HistogramVec,
+    pub latency_jitter_seconds: HistogramVec,
+    pub stream_connected: IntGaugeVec,
+    pub signatures_detected: IntCounterVec,
+    pub id_out_of_bounds: IntCounterVec,
+    pub metadata_conflicts: IntCounterVec,
+    pub grouping_key_missing: IntCounterVec,
+    pub signatures_unmatched: IntCounterVec,
+    pub signatures_rate_limited: IntCounterVec,
+    pub signatures_too_short: IntCounterVec,
+    pub encrypted_payloads: IntCounterVec,
+    pub misaligned_payloads: IntCounterVec,
+    pub audio_buffer_chunks: IntGaugeVec,
+    pub audio_buffer_bytes: IntGaugeVec,
+    pub measurement_enabled: IntGaugeVec,
+    pub measurement_state: IntGaugeVec,
+    pub measurements_active: IntGauge,
+    pub measurements_queued: IntGauge,
+    pub local_signature_queue_depth: IntGauge,
+}
+
+impl SensorMetrics {
+    pub fn register(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(Self {
+            signatures_dropped: register(registry, IntCounter::new(
+                "signatures_dropped_total",
+                "Signatures lost because the matcher lagged behind the broadcast channel",
+            ))?,
+            negative_latency: register(registry, IntCounterVec::new(
+                Opts::new("negative_latency_total", "Matches observed before their signature (clock skew or misconfiguration); excluded from latency stats"),
+                &["measurement"],
+            ))?,
+            duplicate_matches: register(registry, IntCounterVec::new(
+                Opts::new("duplicate_matches_total", "Repeat matches of a signature inside its match_dedup_ms window; not recorded as latency"),
+                &["measurement"],
+            ))?,
+            signatures_missing_metadata: register(registry, IntCounterVec::new(
+                Opts::new("signatures_missing_metadata_total", "Signatures detected without all of their grouping_key ids (handled per missing_metadata_policy)"),
+                &["measurement"],
+            ))?,
+            packets_sampled: register(registry, IntCounterVec::new(
+                Opts::new("packets_sampled_total", "Packets that passed sampling_rate and were run through VAD"),
+                &["measurement"],
+            ))?,
+            stream_rows: register(registry, IntCounterVec::new(
+                Opts::new("stream_rows_total", "Rows received from each measurement's stream_filter"),
+                &["measurement"],
+            ))?,
+            packets_processed: register(registry, IntCounterVec::new(
+                Opts::new("packets_processed_total", "Packets read from Pixie, by measurement (blank for the matcher's unattributed traffic) and role (source/relay)"),
+                &["measurement", "role"],
+            ))?,
+            bytes_processed: register(registry, IntCounterVec::new(
+                Opts::new("bytes_processed_total", "Payload bytes read from Pixie, by measurement and role (source/relay)"),
+                &["measurement", "role"],
+            ))?,
+            stream_idle: register(registry, IntGaugeVec::new(
+                Opts::new("stream_idle", "1 while a measurement's stream_filter has produced no rows for idle_warning_secs"),
+                &["measurement"],
+            ))?,
+            // 10µs .. ~20ms: a 20ms packet analysed slower than that is falling behind
+            packet_processing_seconds: register(registry, HistogramVec::new(
+                HistogramOpts::new("detector_packet_processing_seconds", "Detector time spent on each sampled packet (buffering, VAD, fingerprinting)")
+                    .buckets(prometheus::exponential_buckets(0.00001, 2.0, 12)?),
+                &["measurement"],
+            ))?,
+            // 100µs .. ~400ms of RFC 3550 jitter between consecutive matches
+            latency_jitter_seconds: register(registry, HistogramVec::new(
+                HistogramOpts::new("audio_latency_jitter_seconds", "RFC 3550 running jitter of match latency, tracked per correlation group and reported per measurement")
+                    .buckets(prometheus::exponential_buckets(0.0001, 2.0, 13)?),
+                &["measurement"],
+            ))?,
+            stream_connected: register(registry, IntGaugeVec::new(
+                Opts::new("stream_connected", "1 while a measurement's Pixie stream is open, 0 while reconnecting or after it ended"),
+                &["measurement"],
+            ))?,
+            signatures_detected: register(registry, IntCounterVec::new(
+                Opts::new("signatures_detected_total", "Signatures generated by the detector, before rate limiting"),
+                &["measurement"],
+            ))?,
+            id_out_of_bounds: register(registry, IntCounterVec::new(
+                Opts::new("id_extraction_out_of_bounds_total", "Binary id markers found whose value_offset/value_length runs outside the payload"),
+                &["measurement", "id_type"],
+            ))?,
+            metadata_conflicts: register(registry, IntCounterVec::new(
+                Opts::new("metadata_conflicts_total", "Packets whose id disagreed with one already seen in the same interval (first value kept)"),
+                &["measurement", "id_type"],
+            ))?,
+            grouping_key_missing: register(registry, IntCounterVec::new(
+                Opts::new("grouping_key_missing_total", "Matches left out of per-group stats because a grouping_key field was absent"),
+                &["measurement"],
+            ))?,
+            signatures_unmatched: register(registry, IntCounterVec::new(
+                Opts::new("signatures_unmatched_total", "Signatures that reached their TTL without ever being matched"),
+                &["measurement"],
+            ))?,
+            signatures_rate_limited: register(registry, IntCounterVec::new(
+                Opts::new("signatures_rate_limited_total", "Signatures dropped by the per-measurement broadcast rate limit"),
+                &["measurement"],
+            ))?,
+            signatures_too_short: register(registry, IntCounterVec::new(
+                Opts::new("signatures_too_short_total", "Signatures rejected by the matcher for falling below min_signature_duration_ms"),
+                &["measurement"],
+            ))?,
+            encrypted_payloads: register(registry, IntCounterVec::new(
+                Opts::new("encrypted_payloads_total", "Payloads treated as SRTP ciphertext and correlated on timing only"),
+                &["measurement"],
+            ))?,
+            misaligned_payloads: register(registry, IntCounterVec::new(
+                Opts::new("misaligned_payloads_total", "Audio payloads whose length isn't a whole number of sample frames"),
+                &["measurement"],
+            ))?,
+            audio_buffer_chunks: register(registry, IntGaugeVec::new(
+                Opts::new("audio_buffer_chunks", "Packets currently held in detector audio buffers, summed over streams"),
+                &["measurement"],
+            ))?,
+            audio_buffer_bytes: register(registry, IntGaugeVec::new(
+                Opts::new("audio_buffer_bytes", "Payload bytes currently held in detector audio buffers, summed over streams"),
+                &["measurement"],
+            ))?,
+            measurement_enabled: register(registry, IntGaugeVec::new(
+                Opts::new("measurement_enabled", "1 while a measurement is switched on at runtime, 0 after POST /measurements/{name}/enabled turns it off"),
+                &["measurement"],
+            ))?,
+            measurement_state: register(registry, IntGaugeVec::new(
+                Opts::new("measurement_state", "1 for a measurement's current state: running (rows arriving), idle (no rows for idle_warning_secs) or errored (stopped on an error)"),
+                &["measurement", "state"],
+            ))?,
+            measurements_active: register(registry, IntGauge::new(
+                "measurements_active",
+                "Measurement tasks currently streaming from Pixie",
+            ))?,
+            measurements_queued: register(registry, IntGauge::new(
+                "measurements_queued",
+                "Measurement tasks waiting for a max_concurrent_measurements slot",
+            ))?,
+            local_signature_queue_depth: register(registry, IntGauge::new(
+                "local_signature_queue_depth",
+                "Signatures waiting in the bounded detector-to-matcher queue",
+            ))?,
+        })
+    }
+    
+    /// Packet and byte counters for traffic a `role` ("source" or "relay")
+    /// reads for `measurement` (blank when the traffic isn't attributed)
+    pub fn traffic(&self, measurement: &str, role: &str) -> TrafficCounters {
+        TrafficCounters {
+            packets: self.packets_processed.with_label_values(&[measurement, role]),
+            bytes: self.bytes_processed.with_label_values(&[measurement, role]),
+        }
+    }
+}
+
+/// One measurement and role's series of packets_processed and bytes_processed
+pub struct TrafficCounters {
+    packets: IntCounter,
+    bytes: IntCounter,
+}
+
+impl TrafficCounters {
+    pub fn record(&self, payload: &[u8]) {
+        self.packets.inc();
+        self.bytes.inc_by(payload.len() as u64);
+    }
+}
+
+/// Register `metric` in `registry`, keeping a handle to it
+fn register<M: Collector + Clone + 'static>(registry: &Registry, metric: prometheus::Result<M>) -> prometheus::Result<M> {
+    let metric = metric?;
+    registry.register(Box::new(metric.clone()))?;
+    Ok(metric)
+}
+
+/// Health of one measurement task, exported through measurement_state so
+/// quiet traffic (idle) and a failed detector (errored) look different
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasurementState {
+    Running,
+    Idle,
+    Errored,
+}
+
+impl MeasurementState {
+    const ALL: [MeasurementState; 3] = [Self::Running, Self::Idle, Self::Errored];
+    
+    fn label(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Idle => "idle",
+            Self::Errored => "errored",
+        }
+    }
+    
+    /// Make this `measurement`'s current state: 1 on its series, 0 on the others
+    pub fn set(self, metrics: &SensorMetrics, measurement: &str) {
+        for state in Self::ALL {
+            metrics.measurement_state
+                .with_label_values(&[measurement, state.label()])
+                .set((state == self) as i64);
+        }
+    }
+}
+
+/// Metrics in a registry of their own, so tests never see each other's counts
+#[cfg(test)]
+pub fn test_metrics() -> SensorMetrics {
+    SensorMetrics::register(&Registry::new()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn instances_in_separate_registries_stay_independent() {
+        let (first_registry, second_registry) = (Registry::new(), Registry::new());
+        let first = SensorMetrics::register(&first_registry).unwrap();
+        let second = SensorMetrics::register(&second_registry).unwrap();
+        
+        first.signatures_dropped.inc_by(3);
+        assert_eq!(first.signatures_dropped.get(), 3);
+        assert_eq!(second.signatures_dropped.get(), 0);
+        
+        // Registering twice in one registry is the collision the struct avoids
+        assert!(SensorMetrics::register(&first_registry).is_err());
+    }
+    
+    #[test]
+    fn measurement_state_is_one_hot() {
+        let metrics = test_metrics();
+        MeasurementState::Running.set(&metrics, "m");
+        MeasurementState::Errored.set(&metrics, "m");
+        
+        let state = |label: &str| metrics.measurement_state.with_label_values(&["m", label]).get();
+        assert_eq!((state("running"), state("idle"), state("errored")), (0, 0, 1));
+    }
+    
+    #[test]
+    fn latency_is_observed_end_to_end_and_network_only_in_seconds() {
+        let registry = Registry::new();
+        let latency = LatencyMetrics::register(&[crate::config::test_measurement()], &registry).unwrap();
+        let labels = latency.labels("test", &MetricsConfig::default(), |_| None);
+        latency.observe(&labels, Duration::from_millis(250), Some(Duration::from_millis(100)), None);
+        // Detection finished after the relay capture: end-to-end only
+        latency.observe(&labels, Duration::from_millis(750), None, None);
+        
+        let families = registry.gather();
+        let histograms: Vec<(&str, u64, f64)> = families.iter()
+            .map(|family| {
+                let histogram = family.get_metric()[0].get_histogram();
+                (family.get_name(), histogram.get_sample_count(), histogram.get_sample_sum())
+            })
+            .collect();
+        assert_eq!(histograms, vec![
+            ("audio_latency_seconds", 2, 1.0),
+            ("audio_network_latency_seconds", 1, 0.1),
+        ]);
+    }
+    
+    #[test]
+    fn label_values_are_filtered_or_bucketed() {
+        let filter = MetricsConfig {
+            allow_labels: Some(vec!["measurement".to_string(), "pod".to_string()]),
+            deny_labels: vec!["pod".to_string()],
+            hash_labels: vec!["measurement".to_string()],
+            hash_buckets: 4,
+            ..MetricsConfig::default()
+        };
+        assert_eq!(filter.label_value("interval_id", "42"), "");
+        assert_eq!(filter.label_value("pod", "relay-0"), "");
+        
+        // Bucketed values are stable and bounded
+        let bucket = filter.label_value("measurement", "calls");
+        assert_eq!(bucket, filter.label_value("measurement", "calls"));
+        let distinct: std::collections::HashSet<String> = (0..100)
+            .map(|i| filter.label_value("measurement", &i.to_string()))
+            .collect();
+        assert!(distinct.len() <= 4 && distinct.iter().all(|b| b.starts_with('h')));
+        
+        assert_eq!(MetricsConfig::default().label_value("pod", "relay-0"), "relay-0");
+    }
+    
+    #[test]
+    fn each_measurement_fills_only_its_own_labels() {
+        let mut calls = crate::config::test_measurement();
+        calls.name = "calls".to_string();
+        calls.metric_labels = vec!["relay_pod".to_string()];
+        let mut music = crate::config::test_measurement();
+        music.name = "music".to_string();
+        music.metric_labels = vec!["interval_id".to_string()];
+        let latency = LatencyMetrics::register(&[calls, music], &Registry::new()).unwrap();
+        
+        let lookup = |name: &str| Some(format!("{}-value", name));
+        let labels = latency.labels("calls", &MetricsConfig::default(), lookup);
+        assert_eq!(labels, vec![
+            ("measurement".to_string(), "calls".to_string()),
+            ("relay_pod".to_string(), "relay_pod-value".to_string()),
+            ("interval_id".to_string(), String::new()),
+        ]);
+        let labels = latency.labels("music", &MetricsConfig::default(), |_| None);
+        assert_eq!(labels[2], ("interval_id".to_string(), "unknown".to_string()));
+        
+        let mut bad = crate::config::test_measurement();
+        bad.metric_labels = vec!["customer".to_string()];
+        assert!(LatencyMetrics::register(&[bad], &Registry::new()).is_err());
+    }
+    
+    #[test]
+    fn traffic_is_counted_per_measurement_and_role() {
+        let metrics = test_metrics();
+        let source = metrics.traffic("calls", "source");
+        source.record(&[0; 160]);
+        source.record(&[0; 99]);
+        metrics.traffic("", "relay").record(&[0; 20]);
+        
+        let count = |measurement: &str, role: &str| (
+            metrics.packets_processed.with_label_values(&[measurement, role]).get(),
+            metrics.bytes_processed.with_label_values(&[measurement, role]).get(),
+        );
+        assert_eq!(count("calls", "source"), (2, 259));
+        assert_eq!(count("", "relay"), (1, 20));
+        assert_eq!(count("calls", "relay"), (0, 0));
+    }
+}
@@ -0,0 +1,198 @@
+use prometheus::proto::{MetricFamily, MetricType};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Content type of `encode`'s output
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// One observation shown against the bucket it landed in
+#[derive(Debug, Clone)]
+struct Exemplar {
+    label: (String, String),
+    value: f64,
+    /// Unix seconds
+    timestamp: f64,
+}
+
+/// Latest exemplar per histogram bucket, keyed by (metric, series labels,
+/// bucket upper bound). Bounded by the histograms' own series count.
+#[derive(Default)]
+pub struct Exemplars {
+    latest: Mutex<HashMap<(String, String, u64), Exemplar>>,
+}
+
+impl Exemplars {
+    /// Remember `value` as the exemplar of the bucket in `bounds` it falls in
+    pub fn record(&self, metric: &str, labels: &[(String, String)], bounds: &[f64], value: f64, label: (&str, &str)) {
+        let upper = bounds.iter().copied().find(|bound| value <= *bound).unwrap_or(f64::INFINITY);
+        let series = series_key(labels.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        
+        self.latest.lock().unwrap().insert(
+            (metric.to_string(), series, upper.to_bits()),
+            Exemplar { label: (label.0.to_string(), label.1.to_string()), value, timestamp },
+        );
+    }
+}
+
+/// Render gathered metrics as OpenMetrics text, with each histogram bucket's
+/// latest exemplar and the closing `# EOF`
+pub fn encode(families: &[MetricFamily], exemplars: &Exemplars) -> String {
+    let latest = exemplars.latest.lock().unwrap();
+    let mut out = String::new();
+    
+    for family in families {
+        let name = family.get_name();
+        let kind = family.get_field_type();
+        // OpenMetrics names the counter family without its _total suffix
+        let base = match kind {
+            MetricType::COUNTER => name.strip_suffix("_total").unwrap_or(name),
+            _ => name,
+        };
+        let type_name = match kind {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", base, type_name);
+        let _ = writeln!(out, "# HELP {} {}", base, escape(family.get_help()));
+        
+        for metric in family.get_metric() {
+            let labels: Vec<(&str, &str)> = metric.get_label().iter()
+                .map(|pair| (pair.get_name(), pair.get_value()))
+                .collect();
+            
+            match kind {
+                MetricType::COUNTER => {
+                    sample(&mut out, &format!("{}_total", base), &labels, None, metric.get_counter().get_value(), None);
+                }
+                MetricType::GAUGE => sample(&mut out, base, &labels, None, metric.get_gauge().get_value(), None),
+                MetricType::UNTYPED => sample(&mut out, base, &labels, None, metric.get_untyped().get_value(), None),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let series = series_key(labels.iter().copied());
+                    let bucket_name = format!("{}_bucket", base);
+                    let exemplar = |upper: f64| latest.get(&(name.to_string(), series.clone(), upper.to_bits()));
+                    
+                    for bucket in histogram.get_bucket() {
+                        let upper = bucket.get_upper_bound();
+                        let le = format_value(upper);
+                        let count = bucket.get_cumulative_count() as f64;
+                        sample(&mut out, &bucket_name, &labels, Some(("le", &le)), count, exemplar(upper));
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    sample(&mut out, &bucket_name, &labels, Some(("le", "+Inf")), count, exemplar(f64::INFINITY));
+                    sample(&mut out, &format!("{}_count", base), &labels, None, count, None);
+                    sample(&mut out, &format!("{}_sum", base), &labels, None, histogram.get_sample_sum(), None);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = format_value(quantile.get_quantile());
+                        sample(&mut out, base, &labels, Some(("quantile", &q)), quantile.get_value(), None);
+                    }
+                    sample(&mut out, &format!("{}_count", base), &labels, None, summary.get_sample_count() as f64, None);
+                    sample(&mut out, &format!("{}_sum", base), &labels, None, summary.get_sample_sum(), None);
+                }
+            }
+        }
+    }
+    
+    out.push_str("# EOF\n");
+    out
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    extra: Option<(&str, &str)>,
+    value: f64,
+    exemplar: Option<&Exemplar>,
+) {
+    out.push_str(name);
+    let pairs: Vec<String> = labels.iter()
+        .copied()
+        .chain(extra)
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    if !pairs.is_empty() {
+        let _ = write!(out, "{{{}}}", pairs.join(","));
+    }
+    let _ = write!(out, " {}", format_value(value));
+    
+    if let Some(exemplar) = exemplar {
+        let (name, value) = &exemplar.label;
+        let _ = write!(out, " # {{{}=\"{}\"}} {} {:.3}",
+            name, escape(value), format_value(exemplar.value), exemplar.timestamp);
+    }
+    out.push('\n');
+}
+
+/// Identifies a series regardless of label order; blank labels are the same
+/// as absent ones
+fn series_key<'a>(labels: impl Iterator<Item = (&'a str, &'a str)>) -> String {
+    let mut pairs: Vec<String> = labels
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    pairs.sort();
+    pairs.join("\u{1f}")
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{HistogramOpts, HistogramVec, IntCounter, Registry};
+    
+    #[test]
+    fn histograms_carry_bucket_exemplars_and_the_output_ends_in_eof() {
+        let registry = Registry::new();
+        let opts = HistogramOpts::new("latency_seconds", "Latency").buckets(vec![0.1, 1.0]);
+        let latency = HistogramVec::new(opts, &["pod"]).unwrap();
+        let matches = IntCounter::new("matches_total", "Say \"hi\"").unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(matches.clone())).unwrap();
+        
+        latency.with_label_values(&["relay-0"]).observe(0.25);
+        matches.inc();
+        let exemplars = Exemplars::default();
+        let labels = [("pod".to_string(), "relay-0".to_string())];
+        exemplars.record("latency_seconds", &labels, &[0.1, 1.0], 0.25, ("interval_id", "42"));
+        
+        let text = encode(&registry.gather(), &exemplars);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE matches counter"));
+        assert!(lines.contains(&"# HELP matches Say \\\"hi\\\""));
+        assert!(lines.contains(&"matches_total 1"));
+        assert!(lines.contains(&"latency_seconds_bucket{pod=\"relay-0\",le=\"0.1\"} 0"));
+        assert!(lines.contains(&"latency_seconds_count{pod=\"relay-0\"} 1"));
+        
+        // Only the bucket the observation landed in shows it
+        let bucket = lines.iter().find(|l| l.starts_with("latency_seconds_bucket{pod=\"relay-0\",le=\"1\"}")).unwrap();
+        assert!(bucket.starts_with("latency_seconds_bucket{pod=\"relay-0\",le=\"1\"} 1 # {interval_id=\"42\"} 0.25 "), "{}", bucket);
+        assert!(!lines.iter().any(|l| l.contains("+Inf") && l.contains('#')));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config::ParquetExportConfig;
+use crate::http::MatchRecord;
+use arrow::array::{ArrayRef, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tokio::sync::{broadcast, watch};
+
+/// Streams match records into a Parquet file, one row group per batch
+pub struct ParquetExporter {
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+    pending: Vec<MatchRecord>,
+    row_group_size: usize,
+}
+
+impl ParquetExporter {
+    pub fn create(config: &ParquetExportConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let schema: SchemaRef = Arc::new(Schema::new(vec![
+            Field::new("measurement", DataType::Utf8, false),
+            Field::new("interval_id", DataType::Utf8, false),
+            Field::new("source_pod", DataType::Utf8, true),
+            Field::new("relay_pod", DataType::Utf8, false),
+            Field::new("matched_at_ms", DataType::UInt64, false),
+            Field::new("latency_ns", DataType::UInt64, false),
+        ]));
+        
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(config.row_group_size)
+            .build();
+        let file = File::create(&config.path)?;
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+        
+        Ok(Self {
+            schema,
+            writer,
+            pending: Vec::with_capacity(config.row_group_size),
+            row_group_size: config.row_group_size,
+        })
+    }
+    
+    /// Buffer a record, writing a row group once enough have accumulated
+    pub fn append(&mut self, record: MatchRecord) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending.push(record);
+        if self.pending.len() >= self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+    
+    /// Write buffered records as a row group, even if it's short
+    pub fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        
+        let records = std::mem::take(&mut self.pending);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.measurement.as_str()))),
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.interval_id.as_str()))),
+            Arc::new(StringArray::from_iter(records.iter().map(|r| r.source_pod.as_deref()))),
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.relay_pod.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(records.iter().map(|r| r.matched_at_ms))),
+            Arc::new(UInt64Array::from_iter_values(records.iter().map(|r| (r.latency_ms * 1_000_000.0) as u64))),
+        ];
+        
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+    
+    /// Flush and write the footer; the file isn't readable until this runs
+    pub fn close(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+/// Drain the live match feed into Parquet on a dedicated thread (file I/O
+/// blocks). Once `shutdown` fires, matches already queued are written and the
+/// file is closed; join the handle before exiting or the footer is lost.
+pub fn spawn_exporter(
+    config: ParquetExportConfig,
+    mut matches: broadcast::Receiver<MatchRecord>,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || {
+        let mut exporter = match ParquetExporter::create(&config) {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                println!("⚠️ Parquet export to {} disabled: {}", config.path, e);
+                return;
+            }
+        };
+        
+        loop {
+            let next = runtime.block_on(async {
+                tokio::select! {
+                    biased;
+                    record = matches.recv() => Some(record),
+                    _ = shutdown.changed() => None,
+                }
+            });
+            let Some(next) = next else { break };
+            
+            match next {
+                Ok(record) => {
+                    if let Err(e) = exporter.append(record) {
+                        println!("⚠️ Parquet write failed: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("⚠️ Parquet exporter lagged, {} matches not exported", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        
+        if let Err(e) = exporter.close() {
+            println!("⚠️ Failed to finalize {}: {}", config.path, e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    
+    fn record(latency_ms: f64) -> MatchRecord {
+        MatchRecord {
+            measurement: "test".to_string(),
+            interval_id: "42".to_string(),
+            latency_ms,
+            source_pod: None,
+            relay_pod: "relay-0".to_string(),
+            matched_at_ms: 1_700_000_000_000,
+            source_fingerprint: None,
+            relay_fingerprint: None,
+        }
+    }
+    
+    fn rows(path: &std::path::Path) -> usize {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        reader.map(|batch| batch.unwrap().num_rows()).sum()
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_writes_queued_matches_and_footer() {
+        let path = std::env::temp_dir().join(format!("parquet-export-{}.parquet", std::process::id()));
+        let config = ParquetExportConfig { path: path.display().to_string(), row_group_size: 100 };
+        let (match_tx, _) = broadcast::channel(16);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        
+        let exporter = spawn_exporter(config, match_tx.subscribe(), shutdown_rx);
+        for latency_ms in [1.5, 2.5, 3.5] {
+            match_tx.send(record(latency_ms)).unwrap();
+        }
+        shutdown_tx.send(true).unwrap();
+        tokio::task::spawn_blocking(move || exporter.join().unwrap()).await.unwrap();
+        
+        // Fewer rows than a row group, yet all present and readable
+        assert_eq!(rows(&path), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+}
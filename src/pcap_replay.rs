@@ -0,0 +1,527 @@
+use crate::config::{MeasurementConfig, PcapReplayConfig, ReplayProtocol};
+use crate::fingerprint::FingerprintMatcher;
+use crate::metrics::SensorMetrics;
+use crate::signature_detector::{SignatureDetector, SignatureEvent};
+use crate::signature_store::SignatureStore;
+use crate::tone_detector::ToneMatcher;
+use pcap_parser::{create_reader, Block, Linktype, PcapBlockOwned, PcapError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// One direction of a TCP/UDP conversation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src: (IpAddr, u16),
+    pub dst: (IpAddr, u16),
+}
+
+impl std::fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} -> {}:{}", self.src.0, self.src.1, self.dst.0, self.dst.1)
+    }
+}
+
+/// A reassembled payload chunk with its capture time (since the epoch)
+#[derive(Debug, Clone)]
+pub struct FlowPacket {
+    pub flow: FlowKey,
+    pub timestamp: Duration,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayMatch {
+    pub measurement: String,
+    pub hash: u64,
+    pub source_flow: FlowKey,
+    pub relay_flow: FlowKey,
+    pub latency: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub packets: usize,
+    pub signatures: usize,
+    pub matches: Vec<ReplayMatch>,
+}
+
+/// Drive a capture through the same detector/matching logic used online
+pub fn replay(
+    path: &str,
+    config: &PcapReplayConfig,
+    measurements: &[MeasurementConfig],
+    hash_seed: u64,
+    metrics: &SensorMetrics,
+) -> Result<ReplayReport, Box<dyn std::error::Error>> {
+    let packets = read_flows(path, config)?;
+    let mut report = ReplayReport {
+        packets: packets.len(),
+        ..Default::default()
+    };
+    
+    // One detector per (measurement, flow), mirroring the online per-connection sharding
+    let mut detectors: HashMap<(String, FlowKey), SignatureDetector> = HashMap::new();
+    // Signatures awaiting a match, each tagged with its flow as the source
+    let store = SignatureStore::new();
+    let mut flows: HashMap<String, FlowKey> = HashMap::new();
+    let mut fingerprint_matcher = FingerprintMatcher::new(measurements);
+    let mut tone_matcher = ToneMatcher::new(measurements);
+    
+    let mut pacer = config.speed.filter(|speed| *speed > 0.0).map(Pacer::new);
+    
+    for packet in &packets {
+        if let Some(pacer) = &mut pacer {
+            pacer.wait_for(packet.timestamp);
+        }
+        let flow_name = packet.flow.to_string();
+        flows.entry(flow_name.clone()).or_insert_with(|| packet.flow.clone());
+        let captured_at_ns = packet.timestamp.as_nanos() as u64;
+        
+        // Matcher side: the same lookups as run_signature_matcher
+        let hash = xxhash_rust::xxh3::xxh3_64_with_seed(&packet.payload, hash_seed);
+        let mut candidates: Vec<SignatureEvent> = store.take_any(hash, None).into_iter().collect();
+        candidates.extend(fingerprint_matcher.observe(&flow_name, &packet.payload, &store, hash_seed)
+            .into_iter()
+            .map(|(sig, _)| sig));
+        candidates.extend(tone_matcher.observe(&flow_name, &packet.payload, &store, hash_seed));
+        
+        for sig in candidates {
+            // A flow repeating its own audio isn't a hop; leave it for the real relay
+            let Some(source_flow) = sig.source_pod.as_ref().and_then(|name| flows.get(name)).cloned() else {
+                continue;
+            };
+            if source_flow == packet.flow {
+                store.insert(sig);
+                continue;
+            }
+            // Out-of-order capture timestamps, like skewed clocks online, give no latency
+            let Some(latency) = sig.latency_to(captured_at_ns) else {
+                continue;
+            };
+            report.matches.push(ReplayMatch {
+                measurement: sig.measurement_name.clone(),
+                hash: sig.signature.hash,
+                source_flow,
+                relay_flow: packet.flow.clone(),
+                latency,
+            });
+        }
+        
+        // Detector side
+        for measurement in measurements.iter().filter(|m| m.enabled) {
+            let detector = detectors
+                .entry((measurement.name.clone(), packet.flow.clone()))
+                .or_insert_with(|| SignatureDetector::new(measurement.clone(), hash_seed, metrics));
+            
+            if let Some(mut sig_event) = detector.process_packet(&packet.payload, captured_at_ns)? {
+                report.signatures += 1;
+                sig_event.source_pod = Some(flow_name.clone());
+                store.insert(sig_event);
+            }
+        }
+    }
+    
+    Ok(report)
+}
+
+/// Sleeps so packets are processed at their captured spacing, scaled by `speed`
+struct Pacer {
+    speed: f64,
+    // (first packet's capture time, when we processed it)
+    origin: Option<(Duration, Instant)>,
+}
+
+impl Pacer {
+    fn new(speed: f64) -> Self {
+        Self { speed, origin: None }
+    }
+    
+    fn wait_for(&mut self, timestamp: Duration) {
+        let (first_ts, started) = *self.origin.get_or_insert((timestamp, Instant::now()));
+        let due = started + timestamp.saturating_sub(first_ts).div_f64(self.speed);
+        if let Some(delay) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+/// Read a .pcap/.pcapng file into per-flow payload chunks in capture order,
+/// keeping only traffic on the configured ports/protocol
+pub fn read_flows(path: &str, config: &PcapReplayConfig) -> Result<Vec<FlowPacket>, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let mut reader = create_reader(65536, file)?;
+    
+    let mut packets = Vec::new();
+    let mut legacy_linktype = Linktype::ETHERNET;
+    let mut legacy_nanos = false;
+    // (linktype, if_tsresol, if_tsoffset) per pcapng interface, by interface id
+    let mut interfaces: Vec<(Linktype, u8, u64)> = Vec::new();
+    // Next expected TCP sequence per flow, to drop retransmissions
+    let mut next_seq: HashMap<FlowKey, u32> = HashMap::new();
+    
+    loop {
+        match reader.next() {
+            Ok((offset, block)) => {
+                let frame = match block {
+                    PcapBlockOwned::LegacyHeader(header) => {
+                        legacy_linktype = header.network;
+                        // The a1b23c4d magic stores nanoseconds in the ts_usec field
+                        legacy_nanos = header.is_nanosecond_precision();
+                        None
+                    }
+                    PcapBlockOwned::Legacy(packet) => {
+                        let subsec = if legacy_nanos {
+                            Some(packet.ts_usec)
+                        } else {
+                            packet.ts_usec.checked_mul(1000)
+                        };
+                        let subsec = subsec
+                            .ok_or_else(|| format!("pcap timestamp out of range: {}us", packet.ts_usec))?;
+                        let ts = Duration::new(packet.ts_sec as u64, subsec);
+                        Some((legacy_linktype, ts, packet.data))
+                    }
+                    PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                        interfaces.push((idb.linktype, idb.if_tsresol, idb.if_tsoffset));
+                        None
+                    }
+                    PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                        let ticks = ((epb.ts_high as u64) << 32) | epb.ts_low as u64;
+                        let (linktype, tsresol, tsoffset) = interfaces
+                            .get(epb.if_id as usize)
+                            .copied()
+                            .unwrap_or((Linktype::ETHERNET, DEFAULT_TSRESOL, 0));
+                        let ts = ticks_to_duration(ticks, tsresol);
+                        // if_tsoffset: whole seconds to add to every timestamp
+                        let ts = ts
+                            .checked_add(Duration::from_secs(tsoffset))
+                            .ok_or_else(|| format!("pcapng if_tsoffset out of range: {}s", tsoffset))?;
+                        Some((linktype, ts, epb.data))
+                    }
+                    _ => None,
+                };
+                
+                if let Some((linktype, timestamp, data)) = frame {
+                    if let Some((flow, seq, payload)) = parse_frame(linktype, data, config) {
+                        if accept_segment(&mut next_seq, &flow, seq, payload.len()) {
+                            packets.push(FlowPacket {
+                                flow,
+                                timestamp,
+                                payload: payload.to_vec(),
+                            });
+                        }
+                    }
+                }
+                
+                reader.consume(offset);
+            }
+            Err(PcapError::Eof) => break,
+            Err(PcapError::Incomplete) => {
+                reader.refill().map_err(|e| format!("pcap refill error: {:?}", e))?;
+            }
+            Err(e) => return Err(format!("pcap read error: {:?}", e).into()),
+        }
+    }
+    
+    Ok(packets)
+}
+
+/// pcapng's if_tsresol when the interface doesn't set one: microseconds
+const DEFAULT_TSRESOL: u8 = 6;
+
+/// Convert a pcapng timestamp in if_tsresol units to time since the epoch.
+/// The high bit selects a power of two, otherwise a power of ten, ticks per second.
+fn ticks_to_duration(ticks: u64, tsresol: u8) -> Duration {
+    let exponent = (tsresol & 0x7f) as u32;
+    let per_sec: u128 = if tsresol & 0x80 != 0 {
+        1u128.checked_shl(exponent).unwrap_or(u128::MAX)
+    } else {
+        10u128.checked_pow(exponent).unwrap_or(u128::MAX)
+    };
+    let ticks = ticks as u128;
+    let nanos = (ticks % per_sec) * 1_000_000_000 / per_sec;
+    Duration::new((ticks / per_sec) as u64, nanos as u32)
+}
+
+/// Drop empty segments and TCP retransmissions of data we've already seen
+fn accept_segment(next_seq: &mut HashMap<FlowKey, u32>, flow: &FlowKey, seq: Option<u32>, len: usize) -> bool {
+    if len == 0 {
+        return false;
+    }
+    
+    let Some(seq) = seq else { return true };  // UDP
+    let end = seq.wrapping_add(len as u32);
+    
+    match next_seq.get(flow) {
+        // Segment ends at or before what we've consumed: pure retransmit
+        Some(&expected) if (end.wrapping_sub(expected) as i32) <= 0 => false,
+        _ => {
+            next_seq.insert(flow.clone(), end);
+            true
+        }
+    }
+}
+
+/// Strip link/IP/transport headers; returns flow, TCP sequence (None for UDP) and payload
+fn parse_frame<'a>(
+    linktype: Linktype,
+    data: &'a [u8],
+    config: &PcapReplayConfig,
+) -> Option<(FlowKey, Option<u32>, &'a [u8])> {
+    let ip = match linktype {
+        Linktype::ETHERNET => {
+            let mut offset = 14;
+            let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            // 802.1Q VLAN tag
+            if ethertype == 0x8100 {
+                ethertype = u16::from_be_bytes([*data.get(16)?, *data.get(17)?]);
+                offset += 4;
+            }
+            if ethertype != 0x0800 && ethertype != 0x86DD {
+                return None;
+            }
+            data.get(offset..)?
+        }
+        Linktype::LINUX_SLL => data.get(16..)?,
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => data,
+        _ => return None,
+    };
+    
+    let (src_ip, dst_ip, protocol, transport) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // Ignore Ethernet padding past the IP total length
+            let end = total_len.min(ip.len());
+            (IpAddr::from(src), IpAddr::from(dst), ip[9], ip.get(header_len..end)?)
+        }
+        6 => {
+            // Extension headers aren't walked; audio traffic rarely carries them
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let end = (40 + payload_len).min(ip.len());
+            (IpAddr::from(src), IpAddr::from(dst), ip[6], ip.get(40..end)?)
+        }
+        _ => return None,
+    };
+    
+    let (src_port, dst_port, seq, payload) = match (protocol, &config.protocol) {
+        (6, ReplayProtocol::Tcp | ReplayProtocol::Any) => {
+            let data_offset = ((*transport.get(12)? >> 4) as usize) * 4;
+            let seq = u32::from_be_bytes(transport.get(4..8)?.try_into().ok()?);
+            (
+                u16::from_be_bytes([transport[0], transport[1]]),
+                u16::from_be_bytes([transport[2], transport[3]]),
+                Some(seq),
+                transport.get(data_offset..)?,
+            )
+        }
+        (17, ReplayProtocol::Udp | ReplayProtocol::Any) => (
+            u16::from_be_bytes([*transport.first()?, *transport.get(1)?]),
+            u16::from_be_bytes([*transport.get(2)?, *transport.get(3)?]),
+            None,
+            transport.get(8..)?,
+        ),
+        _ => return None,
+    };
+    
+    if !config.ports.is_empty()
+        && !config.ports.contains(&src_port)
+        && !config.ports.contains(&dst_port)
+    {
+        return None;
+    }
+    
+    Some((
+        FlowKey {
+            src: (src_ip, src_port),
+            dst: (dst_ip, dst_port),
+        },
+        seq,
+        payload,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{test_measurement, PayloadEncryption};
+    use crate::metrics::test_metrics;
+    use std::net::Ipv4Addr;
+    
+    /// Ethernet + IPv4 + UDP frame carrying `payload` from port 4000 to 5000
+    fn udp_frame(payload: &[u8]) -> Vec<u8> {
+        udp_frame_between(4000, 5000, payload)
+    }
+    
+    fn udp_frame_between(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+        let total_len = (20 + 8 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1]);
+        frame.extend_from_slice(&[10, 0, 0, 2]);
+        frame.extend_from_slice(&src_port.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        // Ethernet minimum-size padding past the IP total length
+        frame.extend_from_slice(&[0xee; 4]);
+        frame
+    }
+    
+    /// Classic little-endian pcap, microsecond timestamps
+    fn pcap_file(frames: &[(Duration, Vec<u8>)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        for word in [0xa1b2c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+        for (ts, frame) in frames {
+            for word in [ts.as_secs() as u32, ts.subsec_micros(), frame.len() as u32, frame.len() as u32] {
+                file.extend_from_slice(&word.to_le_bytes());
+            }
+            file.extend_from_slice(frame);
+        }
+        file
+    }
+    
+    /// One pcapng block: type, length, body (padded to 32 bits), length again
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let len = (12 + padded) as u32;
+        let mut block = [block_type.to_le_bytes(), len.to_le_bytes()].concat();
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&len.to_le_bytes());
+        block
+    }
+    
+    /// pcapng with one Ethernet interface counting nanoseconds from `tsoffset` seconds
+    fn pcapng_file(tsoffset: u64, frames: &[(Duration, Vec<u8>)]) -> Vec<u8> {
+        let mut section = 0x1a2b3c4du32.to_le_bytes().to_vec();
+        section.extend_from_slice(&[1, 0, 0, 0]);
+        section.extend_from_slice(&(-1i64).to_le_bytes());
+        let mut file = pcapng_block(0x0a0d0d0a, &section);
+        
+        let mut interface = vec![1, 0, 0, 0];
+        interface.extend_from_slice(&65535u32.to_le_bytes());
+        // if_tsresol = 9, if_tsoffset, end of options
+        interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 14, 0, 8, 0]);
+        interface.extend_from_slice(&tsoffset.to_le_bytes());
+        interface.extend_from_slice(&[0, 0, 0, 0]);
+        file.extend(pcapng_block(1, &interface));
+        
+        for (ts, frame) in frames {
+            let ticks = ts.as_nanos() as u64;
+            let mut packet = Vec::new();
+            for word in [0, (ticks >> 32) as u32, ticks as u32, frame.len() as u32, frame.len() as u32] {
+                packet.extend_from_slice(&word.to_le_bytes());
+            }
+            packet.extend_from_slice(frame);
+            file.extend(pcapng_block(6, &packet));
+        }
+        file
+    }
+    
+    #[test]
+    fn pcapng_timestamps_honour_if_tsresol() {
+        assert_eq!(ticks_to_duration(1_500_000, DEFAULT_TSRESOL), Duration::from_millis(1500));
+        assert_eq!(ticks_to_duration(1_000_000_123, 9), Duration::new(1, 123));
+        // Power-of-two resolution: 2^10 ticks per second
+        assert_eq!(ticks_to_duration(1024 + 512, 0x80 | 10), Duration::from_millis(1500));
+    }
+    
+    #[test]
+    fn parses_udp_payload_and_ignores_padding() {
+        let frame = udp_frame(b"audio");
+        let (flow, seq, payload) = parse_frame(Linktype::ETHERNET, &frame, &PcapReplayConfig::default()).unwrap();
+        
+        assert_eq!(flow.src, (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4000));
+        assert_eq!(flow.dst, (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 5000));
+        assert_eq!(seq, None);
+        assert_eq!(payload, b"audio");
+    }
+    
+    #[test]
+    fn port_and_protocol_filters_apply() {
+        let frame = udp_frame(b"audio");
+        let other_port = PcapReplayConfig { ports: vec![6000], ..Default::default() };
+        let tcp_only = PcapReplayConfig { protocol: ReplayProtocol::Tcp, ..Default::default() };
+        
+        assert!(parse_frame(Linktype::ETHERNET, &frame, &other_port).is_none());
+        assert!(parse_frame(Linktype::ETHERNET, &frame, &tcp_only).is_none());
+    }
+    
+    #[test]
+    fn tcp_retransmissions_are_dropped() {
+        let flow = FlowKey {
+            src: (IpAddr::V4(Ipv4Addr::LOCALHOST), 1),
+            dst: (IpAddr::V4(Ipv4Addr::LOCALHOST), 2),
+        };
+        let mut next_seq = HashMap::new();
+        
+        assert!(accept_segment(&mut next_seq, &flow, Some(100), 10));
+        assert!(!accept_segment(&mut next_seq, &flow, Some(100), 10));
+        assert!(accept_segment(&mut next_seq, &flow, Some(110), 10));
+        assert!(!accept_segment(&mut next_seq, &flow, Some(120), 0));
+    }
+    
+    #[test]
+    fn pacer_replays_capture_gaps_divided_by_speed() {
+        let mut pacer = Pacer::new(10.0);
+        let started = Instant::now();
+        pacer.wait_for(Duration::from_secs(100));
+        pacer.wait_for(Duration::from_secs(100) + Duration::from_millis(500));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50) && elapsed < Duration::from_millis(400), "{:?}", elapsed);
+        
+        // Late or out-of-order packets go out immediately
+        let started = Instant::now();
+        pacer.wait_for(Duration::from_secs(99));
+        assert!(started.elapsed() < Duration::from_millis(20));
+    }
+    
+    #[test]
+    fn pcap_and_pcapng_replays_match_relayed_payloads() {
+        let mut measurement = test_measurement();
+        // Every talkspurt's first packet signs with its own hash, which the relay copy matches
+        measurement.signature_rules.audio_criteria.encryption = PayloadEncryption::Srtp;
+        let payload = vec![0x5a; 160];
+        let frames = |base: Duration| {
+            vec![
+                (base, udp_frame_between(4000, 5000, &payload)),
+                (base + Duration::from_millis(25), udp_frame_between(5002, 6000, &payload)),
+            ]
+        };
+        
+        let pcap = std::env::temp_dir().join(format!("replay-{}.pcap", std::process::id()));
+        let pcapng = std::env::temp_dir().join(format!("replay-{}.pcapng", std::process::id()));
+        std::fs::write(&pcap, pcap_file(&frames(Duration::from_secs(1_700_000_000)))).unwrap();
+        // 1.7e9s of offset plus 1.5ms of ticks lands beside the pcap capture
+        std::fs::write(&pcapng, pcapng_file(1_700_000_000, &frames(Duration::from_micros(1500)))).unwrap();
+        
+        for path in [&pcap, &pcapng] {
+            let report = replay(
+                path.to_str().unwrap(),
+                &PcapReplayConfig::default(),
+                &[measurement.clone()],
+                7,
+                &test_metrics(),
+            ).unwrap();
+            std::fs::remove_file(path).unwrap();
+            
+            assert_eq!((report.packets, report.signatures), (2, 2), "{:?}", path);
+            let [found] = &report.matches[..] else { panic!("{:?}: {:?}", path, report.matches) };
+            assert_eq!(found.hash, xxhash_rust::xxh3::xxh3_64_with_seed(&payload, 7));
+            assert_eq!(found.latency, Duration::from_millis(25));
+            assert_eq!((found.source_flow.src.1, found.relay_flow.src.1), (4000, 5002));
+        }
+    }
+}
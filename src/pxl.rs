@@ -0,0 +1,220 @@
+use crate::config::{MatcherQueryConfig, SensorConfig};
+
+/// `stream_filter` values with this prefix name a script file instead of inline PxL
+const FILE_PREFIX: &str = "file://";
+
+/// Cheap structural check run before a script ever reaches Pixie: brackets must
+/// balance outside string literals and comments. Catches truncated or mis-pasted
+/// scripts; anything subtler is left for Pixie's compiler.
+pub fn check_syntax(script: &str) -> Result<(), String> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut open: Vec<(char, usize)> = Vec::new();
+    // (delimiter, triple-quoted, line opened on)
+    let mut quote: Option<(char, bool, usize)> = None;
+    let mut line = 1;
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        let triple = chars[i..].starts_with(&[c, c, c]);
+        
+        if let Some((q, long, at)) = quote {
+            match c {
+                '\\' => i += 1,
+                '\n' if !long => return Err(format!("line {}: unterminated {} string", at, q)),
+                _ if c == q && (!long || triple) => {
+                    quote = None;
+                    if long {
+                        i += 2;
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            match c {
+                '#' => {
+                    while i + 1 < chars.len() && chars[i + 1] != '\n' {
+                        i += 1;
+                    }
+                }
+                '\'' | '"' => {
+                    quote = Some((c, triple, line));
+                    if triple {
+                        i += 2;
+                    }
+                }
+                '(' | '[' | '{' => open.push((c, line)),
+                ')' | ']' | '}' => {
+                    let expected = match c {
+                        ')' => '(',
+                        ']' => '[',
+                        _ => '{',
+                    };
+                    match open.pop() {
+                        Some((o, _)) if o == expected => {}
+                        Some((o, at)) => {
+                            return Err(format!("line {}: '{}' closes '{}' opened on line {}", line, c, o, at));
+                        }
+                        None => return Err(format!("line {}: unmatched '{}'", line, c)),
+                    }
+                }
+                _ => {}
+            }
+        }
+        
+        if chars.get(i) == Some(&'\n') {
+            line += 1;
+        }
+        i += 1;
+    }
+    
+    if let Some((q, _, at)) = quote {
+        return Err(format!("line {}: unterminated {} string", at, q));
+    }
+    match open.pop() {
+        Some((o, at)) => Err(format!("line {}: '{}' is never closed", at, o)),
+        None => Ok(()),
+    }
+}
+
+/// Pixie tables the matcher query may read: only those with the raw `payload`,
+/// `upid` and `local_port`/`remote_port` columns it selects. Pixie's protocol
+/// tables (http_events, dns_events, ...) hold parsed bodies instead.
+pub const KNOWN_TABLES: &[&str] = &["socket_data"];
+
+/// Table names spliced into generated PxL must be one of `KNOWN_TABLES`;
+/// anything else could be a typo, or close the quoted string and inject code
+pub fn check_identifier(name: &str) -> Result<(), String> {
+    if KNOWN_TABLES.contains(&name) {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not a known Pixie table (expected one of: {})", name, KNOWN_TABLES.join(", ")))
+    }
+}
+
+/// The matcher's query for all relayed audio traffic over the configured window.
+/// Every interpolated value is either a number or a checked identifier, so
+/// config can't smuggle PxL into the script.
+pub fn matcher_query(config: &MatcherQueryConfig) -> Result<String, String> {
+    check_identifier(&config.table)?;
+    if config.ports.is_empty() {
+        return Err("no ports to query".to_string());
+    }
+    
+    let port_filter = config.ports.iter()
+        .map(|port| format!("df.local_port == {port} or df.remote_port == {port}"))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    
+    Ok(format!(r#"
+            df = px.DataFrame(table='{}', start_time='{}s')
+            df = df[{}]
+            df[['timestamp', 'pod_name', 'upid', 'payload']]
+        "#, config.table, config.window_secs, port_filter))
+}
+
+/// Inline PxL as-is, or the contents of a `file://` path (relative to the
+/// working directory, like config.yaml itself)
+pub fn resolve(stream_filter: &str) -> Result<String, String> {
+    match stream_filter.strip_prefix(FILE_PREFIX) {
+        Some(path) => std::fs::read_to_string(path.trim())
+            .map_err(|e| format!("reading {}: {}", path.trim(), e)),
+        None => Ok(stream_filter.to_string()),
+    }
+}
+
+impl SensorConfig {
+    /// Replace every `file://` stream_filter with the script it names and
+    /// syntax-check all of them, so a bad script fails at startup rather than
+    /// when its measurement first connects
+    pub fn load_scripts(&mut self) -> Result<(), String> {
+        for m in &mut self.measurements {
+            let rules = &mut m.signature_rules;
+            let script = resolve(&rules.stream_filter)
+                .map_err(|e| format!("measurement '{}': {}", m.name, e))?;
+            check_syntax(&script)
+                .map_err(|e| format!("measurement '{}' stream_filter: {}", m.name, e))?;
+            rules.stream_filter = script;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    
+    #[test]
+    fn file_scripts_load_verbatim_and_are_checked() {
+        let script = "import px\n# comment with an unbalanced (\ndf = px.DataFrame(table='socket_data')\npx.display(df)\n";
+        let path = std::env::temp_dir().join(format!("stream-filter-{}.pxl", std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        
+        let mut config: SensorConfig = serde_yaml::from_str("measurements: []").unwrap();
+        let mut measurement = test_measurement();
+        measurement.signature_rules.stream_filter = format!("file://{}", path.display());
+        config.measurements.push(measurement);
+        config.load_scripts().unwrap();
+        assert_eq!(config.measurements[0].signature_rules.stream_filter, script);
+        
+        std::fs::write(&path, "df = px.DataFrame(table='socket_data'\n").unwrap();
+        config.measurements[0].signature_rules.stream_filter = format!("file://{}", path.display());
+        let error = config.load_scripts().unwrap_err();
+        assert!(error.starts_with("measurement 'test' stream_filter: line 1"), "{}", error);
+        
+        std::fs::remove_file(&path).unwrap();
+        assert!(config.load_scripts().unwrap_err().contains("reading"));
+    }
+    
+    #[test]
+    fn brackets_must_balance_outside_strings_and_comments() {
+        assert!(check_syntax("df = df[['a', 'b']]  # trailing ]").is_ok());
+        assert!(check_syntax("s = '(' + \"[\" + '''\n{\n'''").is_ok());
+        
+        assert_eq!(check_syntax("f(a]").unwrap_err(), "line 1: ']' closes '(' opened on line 1");
+        assert_eq!(check_syntax("x\n)").unwrap_err(), "line 2: unmatched ')'");
+        assert_eq!(check_syntax("f(\n\n").unwrap_err(), "line 1: '(' is never closed");
+        assert_eq!(check_syntax("s = 'open\n").unwrap_err(), "line 1: unterminated ' string");
+    }
+    
+    #[test]
+    fn matcher_query_reaches_back_the_configured_window() {
+        let config = MatcherQueryConfig { window_secs: 45, ..MatcherQueryConfig::default() };
+        let query = matcher_query(&config).unwrap();
+        assert!(query.contains("start_time='45s'"), "{}", query);
+        assert!(check_syntax(&query).is_ok());
+    }
+    
+    #[test]
+    fn configured_table_names_the_queried_dataframe() {
+        let config = MatcherQueryConfig { table: "socket_data".to_string(), ..MatcherQueryConfig::default() };
+        assert!(matcher_query(&config).unwrap().contains("px.DataFrame(table='socket_data',"));
+        
+        // Real Pixie tables, but without the raw payload and port columns the query selects
+        for table in ["http_events", "dns_events", "pgsql_events"] {
+            let config = MatcherQueryConfig { table: table.to_string(), ..MatcherQueryConfig::default() };
+            assert!(matcher_query(&config).is_err(), "{}", table);
+        }
+        
+        let typo = MatcherQueryConfig { table: "socket-data".to_string(), ..MatcherQueryConfig::default() };
+        assert!(matcher_query(&typo).unwrap_err().starts_with("\"socket-data\" is not a known Pixie table"));
+    }
+    
+    #[test]
+    fn injected_table_names_are_rejected() {
+        let malicious = MatcherQueryConfig {
+            table: "socket_data')\npx.display(px.DataFrame(table='secrets'))\n#".to_string(),
+            ..MatcherQueryConfig::default()
+        };
+        assert!(matcher_query(&malicious).is_err());
+        assert!(check_identifier("socket_data ").is_err());
+        
+        let no_ports = MatcherQueryConfig { ports: Vec::new(), ..MatcherQueryConfig::default() };
+        assert_eq!(matcher_query(&no_ports).unwrap_err(), "no ports to query");
+        let ports = MatcherQueryConfig { ports: vec![5004, 8000], ..MatcherQueryConfig::default() };
+        assert!(matcher_query(&ports).unwrap().contains(
+            "df.local_port == 5004 or df.remote_port == 5004 or df.local_port == 8000 or df.remote_port == 8000"
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Instant;
+
+/// Classic token bucket: refills at `rate` tokens/sec up to `burst`
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    /// Take one token if available
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+    
+    pub fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+        
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    
+    #[test]
+    fn burst_then_refill_at_rate() {
+        let mut bucket = TokenBucket::new(10.0, 3.0);
+        let start = bucket.last_refill;
+        
+        assert_eq!((0..4).map(|_| bucket.try_acquire_at(start)).collect::<Vec<_>>(), vec![true, true, true, false]);
+        // 10/sec earns a token every 100ms
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(50)));
+        assert!(bucket.try_acquire_at(start + Duration::from_millis(100)));
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(100)));
+    }
+    
+    #[test]
+    fn idle_time_refills_only_up_to_burst() {
+        let mut bucket = TokenBucket::new(100.0, 2.0);
+        let later = bucket.last_refill + Duration::from_secs(60);
+        
+        assert!(bucket.try_acquire_at(later));
+        assert!(bucket.try_acquire_at(later));
+        assert!(!bucket.try_acquire_at(later));
+        // A clock that steps backwards earns nothing
+        assert!(!bucket.try_acquire_at(later - Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::{AudioFormat, MeasurementConfig, ProtocolType, SampleFormat};
+
+/// The parts of an RTP packet the detector cares about
+pub struct RtpPacket<'a> {
+    pub payload_type: u8,
+    pub payload: &'a [u8],
+}
+
+/// Parse an RTP (v2) header, skipping CSRCs, header extension and padding
+pub fn parse(packet: &[u8]) -> Option<RtpPacket<'_>> {
+    let first = *packet.first()?;
+    if first >> 6 != 2 {
+        return None;
+    }
+    
+    let csrc_count = (first & 0x0f) as usize;
+    let mut offset = 12 + csrc_count * 4;
+    if first & 0x10 != 0 {
+        // Extension: 2 bytes profile, 2 bytes length in 32-bit words
+        let words = u16::from_be_bytes([*packet.get(offset + 2)?, *packet.get(offset + 3)?]) as usize;
+        offset += 4 + words * 4;
+    }
+    
+    let mut end = packet.len();
+    if first & 0x20 != 0 {
+        end = end.checked_sub(*packet.last()? as usize)?;
+    }
+    
+    Some(RtpPacket {
+        payload_type: packet.get(1)? & 0x7f,
+        payload: packet.get(offset..end)?,
+    })
+}
+
+/// Static RFC 3551 payload types this sensor can decode
+fn static_payload_format(payload_type: u8) -> Option<AudioFormat> {
+    let (sample_format, sample_rate, channels) = match payload_type {
+        0 => (SampleFormat::MuLaw, 8000, 1),
+        8 => (SampleFormat::ALaw, 8000, 1),
+        10 => (SampleFormat::S16Be, 44100, 2),
+        11 => (SampleFormat::S16Be, 44100, 1),
+        _ => return None,
+    };
+    Some(AudioFormat { sample_format, sample_rate, channels })
+}
+
+/// The audio bytes of a packet and how to decode them. RTP measurements use
+/// the packet's payload type (config overrides first); everything else is the
+/// whole packet in the measurement's static format. None: not decodable.
+pub fn audio_payload<'a>(packet: &'a [u8], measurement: &MeasurementConfig) -> Option<(&'a [u8], AudioFormat)> {
+    let criteria = &measurement.signature_rules.audio_criteria;
+    
+    match measurement.metadata_extraction.protocol {
+        ProtocolType::RTP => {
+            let rtp = parse(packet)?;
+            let format = criteria.rtp_payload_types.get(&rtp.payload_type)
+                .cloned()
+                .or_else(|| static_payload_format(rtp.payload_type))?;
+            Some((rtp.payload, format))
+        }
+        _ => Some((packet, AudioFormat {
+            sample_format: criteria.sample_format.clone(),
+            sample_rate: criteria.sample_rate,
+            channels: 1,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    
+    /// RTP v2 header with the given flag bits and payload type, then `body`
+    fn packet(flags: u8, payload_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80 | flags, payload_type];
+        packet.extend_from_slice(&[0; 10]);
+        packet.extend_from_slice(body);
+        packet
+    }
+    
+    #[test]
+    fn parse_skips_csrcs_extension_and_padding() {
+        // One CSRC, a one-word extension, then padding whose last byte counts it
+        let body = [&[0xc5; 4][..], &[0xbe, 0xde, 0, 1, 0, 0, 0, 0], b"audio", &[0, 0, 3]].concat();
+        let rtp = packet(0x30 | 1, 0x80 | 8, &body);
+        let parsed = parse(&rtp).unwrap();
+        
+        // The marker bit isn't part of the payload type
+        assert_eq!(parsed.payload_type, 8);
+        assert_eq!(parsed.payload, b"audio");
+        
+        assert!(parse(&[0x40; 20]).is_none());
+        assert!(parse(&packet(0x0f, 0, &[])).is_none());
+    }
+    
+    #[test]
+    fn payload_type_picks_the_decode_format() {
+        let mut measurement = test_measurement();
+        measurement.metadata_extraction.protocol = ProtocolType::RTP;
+        
+        let pcmu = packet(0, 0, b"pcmu");
+        let (payload, format) = audio_payload(&pcmu, &measurement).unwrap();
+        assert_eq!((payload, format.sample_format, format.sample_rate), (&b"pcmu"[..], SampleFormat::MuLaw, 8000));
+        assert!(audio_payload(&packet(0, 96, b"opus"), &measurement).is_none());
+        
+        let wideband = AudioFormat { sample_format: SampleFormat::S16Be, sample_rate: 16000, channels: 1 };
+        measurement.signature_rules.audio_criteria.rtp_payload_types.insert(96, wideband.clone());
+        assert_eq!(audio_payload(&packet(0, 96, b"l16"), &measurement).unwrap().1, wideband);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::config::SampleFormat;
+
+impl SampleFormat {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::MuLaw | SampleFormat::ALaw => 1,
+            SampleFormat::S16Le | SampleFormat::S16Be => 2,
+            SampleFormat::S24Le | SampleFormat::S24Be => 3,
+            SampleFormat::F32Le | SampleFormat::F32Be => 4,
+        }
+    }
+}
+
+/// 2^23: full scale of 24-bit PCM
+const S24_SCALE: f32 = 8_388_608.0;
+
+/// Decode raw PCM bytes into samples normalized to -1.0..1.0.
+/// A trailing partial sample is ignored.
+pub fn decode_samples(bytes: &[u8], format: &SampleFormat) -> Vec<f32> {
+    let frames = bytes.chunks_exact(format.bytes_per_sample());
+    
+    match format {
+        SampleFormat::S16Le => frames
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        SampleFormat::S16Be => frames
+            .map(|b| i16::from_be_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        // Packed 24-bit: the 3 bytes go in the top of an i32, and the
+        // arithmetic shift back down sign-extends them
+        SampleFormat::S24Le => frames
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / S24_SCALE)
+            .collect(),
+        SampleFormat::S24Be => frames
+            .map(|b| (i32::from_be_bytes([b[0], b[1], b[2], 0]) >> 8) as f32 / S24_SCALE)
+            .collect(),
+        SampleFormat::MuLaw => frames.map(|b| mulaw_to_linear(b[0]) as f32 / 32768.0).collect(),
+        SampleFormat::ALaw => frames.map(|b| alaw_to_linear(b[0]) as f32 / 32768.0).collect(),
+        // Float PCM is already normalized
+        SampleFormat::F32Le => frames
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        SampleFormat::F32Be => frames
+            .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+    }
+}
+
+/// Encode normalized samples back into raw PCM bytes (inverse of `decode_samples`)
+pub fn encode_samples(samples: &[f32], format: &SampleFormat) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(samples.len() * format.bytes_per_sample());
+    
+    for &sample in samples {
+        let linear = (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+        match format {
+            SampleFormat::S16Le => bytes.extend_from_slice(&linear.to_le_bytes()),
+            SampleFormat::S16Be => bytes.extend_from_slice(&linear.to_be_bytes()),
+            SampleFormat::S24Le => bytes.extend_from_slice(&s24(sample).to_le_bytes()[..3]),
+            SampleFormat::S24Be => bytes.extend_from_slice(&s24(sample).to_be_bytes()[1..]),
+            SampleFormat::MuLaw => bytes.push(linear_to_mulaw(linear)),
+            SampleFormat::ALaw => bytes.push(linear_to_alaw(linear)),
+            SampleFormat::F32Le => bytes.extend_from_slice(&sample.to_le_bytes()),
+            SampleFormat::F32Be => bytes.extend_from_slice(&sample.to_be_bytes()),
+        }
+    }
+    
+    bytes
+}
+
+/// Normalized sample as a 24-bit integer (in an i32)
+fn s24(sample: f32) -> i32 {
+    (sample.clamp(-1.0, 1.0) * (S24_SCALE - 1.0)).round() as i32
+}
+
+/// Average interleaved channels down to mono
+pub fn downmix(samples: Vec<f32>, channels: u16) -> Vec<f32> {
+    if channels <= 1 {
+        return samples;
+    }
+    samples
+        .chunks(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Linearly interpolate `samples` onto `len` evenly spaced points covering
+/// the same span of time
+pub fn resample(samples: &[f32], len: usize) -> Vec<f32> {
+    if samples.len() == len || samples.is_empty() {
+        return samples.to_vec();
+    }
+    
+    let step = samples.len() as f64 / len as f64;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let index = pos as usize;
+            let frac = (pos - index as f64) as f32;
+            let next = samples.get(index + 1).copied().unwrap_or(samples[index]);
+            samples[index] + (next - samples[index]) * frac
+        })
+        .collect()
+}
+
+/// Band-limit `samples` to `low..high` Hz: a second-order Butterworth
+/// high-pass at `low` followed by a low-pass at `high` (RBJ biquads), so the
+/// passband stays flat where a single peaking band-pass would not
+pub fn bandpass(samples: &[f32], sample_rate: u32, (low, high): (f32, f32)) -> Vec<f32> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let mut out = samples.to_vec();
+    if low > 0.0 && low < nyquist {
+        biquad(&mut out, sample_rate, low, true);
+    }
+    if high < nyquist * 0.95 {
+        biquad(&mut out, sample_rate, high, false);
+    }
+    out
+}
+
+/// Filter in place with a Q = 1/sqrt(2) high- or low-pass at `cutoff` Hz
+fn biquad(samples: &mut [f32], sample_rate: u32, cutoff: f32, high_pass: bool) {
+    let w0 = 2.0 * std::f32::consts::PI * cutoff / sample_rate as f32;
+    let alpha = w0.sin() / std::f32::consts::SQRT_2;
+    let cos = w0.cos();
+    let a0 = 1.0 + alpha;
+    let (b0, b1) = if high_pass {
+        ((1.0 + cos) / 2.0, -(1.0 + cos))
+    } else {
+        ((1.0 - cos) / 2.0, 1.0 - cos)
+    };
+    let (b0, b1, b2) = (b0 / a0, b1 / a0, b0 / a0);
+    let (a1, a2) = (-2.0 * cos / a0, (1.0 - alpha) / a0);
+    
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    for sample in samples.iter_mut() {
+        let x = *sample;
+        let y = b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        (x2, x1, y2, y1) = (x1, x, y1, y);
+        *sample = y;
+    }
+}
+
+// G.711 companding (ITU-T G.711 segment tables)
+
+const MULAW_BIAS: i32 = 0x84;
+
+fn mulaw_to_linear(byte: u8) -> i16 {
+    let byte = !byte;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0f) as i32;
+    let magnitude = (((mantissa << 3) + MULAW_BIAS) << exponent) - MULAW_BIAS;
+    if byte & 0x80 != 0 { -magnitude as i16 } else { magnitude as i16 }
+}
+
+fn linear_to_mulaw(sample: i16) -> u8 {
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = (sample as i32).abs().min(32635) + MULAW_BIAS;
+    let exponent = 31 - ((magnitude >> 7) as u32).leading_zeros();
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+fn alaw_to_linear(byte: u8) -> i16 {
+    let byte = byte ^ 0x55;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0f) as i32;
+    let magnitude = match exponent {
+        0 => (mantissa << 4) + 8,
+        e => ((mantissa << 4) + 0x108) << (e - 1),
+    };
+    // A-law's sign bit is set for positive values
+    if byte & 0x80 != 0 { magnitude as i16 } else { -magnitude as i16 }
+}
+
+fn linear_to_alaw(sample: i16) -> u8 {
+    // 13-bit magnitude
+    let (sign, magnitude) = match sample as i32 >> 3 {
+        s if s >= 0 => (0x80, s),
+        s => (0x00, -s - 1),
+    };
+    let magnitude = magnitude.min(0x0fff);
+    let (exponent, mantissa) = if magnitude < 32 {
+        (0, (magnitude >> 1) & 0x0f)
+    } else {
+        let e = 32 - ((magnitude >> 5) as u32).leading_zeros();
+        (e, (magnitude >> e) & 0x0f)
+    };
+    (sign | (exponent << 4) as u8 | mantissa as u8) ^ 0x55
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn f32_decodes_either_byte_order_and_skips_partial_samples() {
+        let mut le = [0.5f32.to_le_bytes(), (-0.25f32).to_le_bytes()].concat();
+        le.extend_from_slice(&[0x00, 0x00]);
+        let be = [0.5f32.to_be_bytes(), (-0.25f32).to_be_bytes()].concat();
+        
+        assert_eq!(decode_samples(&le, &SampleFormat::F32Le), vec![0.5, -0.25]);
+        assert_eq!(decode_samples(&be, &SampleFormat::F32Be), vec![0.5, -0.25]);
+    }
+    
+    #[test]
+    fn f32_round_trips_exactly() {
+        let samples = [0.0, 0.123_456_79, -0.987_654_3, 1.0, -1.0];
+        for format in [SampleFormat::F32Le, SampleFormat::F32Be] {
+            assert_eq!(decode_samples(&encode_samples(&samples, &format), &format), samples);
+        }
+    }
+    
+    #[test]
+    fn g711_decodes_reference_codes_and_round_trips() {
+        // Silence and both extremes of each law
+        assert_eq!(mulaw_to_linear(0xff), 0);
+        assert_eq!((mulaw_to_linear(0x80), mulaw_to_linear(0x00)), (32124, -32124));
+        assert_eq!((alaw_to_linear(0xd5), alaw_to_linear(0x55)), (8, -8));
+        assert_eq!((alaw_to_linear(0xaa), alaw_to_linear(0x2a)), (32256, -32256));
+        
+        // Companding keeps error proportional to the sample
+        let samples = [0.001, -0.01, 0.1, -0.5, 0.9];
+        for format in [SampleFormat::MuLaw, SampleFormat::ALaw] {
+            let decoded = decode_samples(&encode_samples(&samples, &format), &format);
+            for (before, after) in samples.iter().zip(&decoded) {
+                assert!((before - after).abs() <= before.abs() * 0.07 + 0.0005, "{:?}: {} -> {}", format, before, after);
+            }
+        }
+    }
+    
+    #[test]
+    fn bandpass_passes_speech_and_cuts_hum_and_hiss() {
+        let rms_ratio = |hz: f32| {
+            let tone: Vec<f32> = (0..8000).map(|i| (i as f32 / 8000.0 * hz * std::f32::consts::TAU).sin()).collect();
+            let filtered = bandpass(&tone, 8000, (300.0, 3400.0));
+            // Past the filters' settling time
+            let rms = |s: &[f32]| (s[800..].iter().map(|x| x * x).sum::<f32>() / (s.len() - 800) as f32).sqrt();
+            rms(&filtered) / rms(&tone)
+        };
+        
+        assert!((rms_ratio(1000.0) - 1.0).abs() < 0.1, "{}", rms_ratio(1000.0));
+        assert!(rms_ratio(60.0) < 0.1, "{}", rms_ratio(60.0));
+        assert!(rms_ratio(3900.0) < 0.5, "{}", rms_ratio(3900.0));
+        // Butterworth: -3dB at the cutoff
+        assert!((rms_ratio(300.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.05, "{}", rms_ratio(300.0));
+        
+        // From 0Hz to Nyquist: neither filter runs
+        let noise: Vec<f32> = (0..64).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        assert_eq!(bandpass(&noise, 8000, (0.0, 4000.0)), noise);
+    }
+    
+    #[test]
+    fn s24_sign_extends_in_either_byte_order() {
+        // -1, full-scale negative, just under full-scale positive
+        let le = [0xff, 0xff, 0xff, 0x00, 0x00, 0x80, 0xff, 0xff, 0x7f];
+        let be = [0xff, 0xff, 0xff, 0x80, 0x00, 0x00, 0x7f, 0xff, 0xff];
+        let expected = vec![-1.0 / S24_SCALE, -1.0, (S24_SCALE - 1.0) / S24_SCALE];
+        assert_eq!(decode_samples(&le, &SampleFormat::S24Le), expected);
+        assert_eq!(decode_samples(&be, &SampleFormat::S24Be), expected);
+        // A trailing partial sample is dropped
+        assert_eq!(decode_samples(&le[..5], &SampleFormat::S24Le).len(), 1);
+        
+        let samples = [0.0, 0.5, -0.25, 1.0, -1.0];
+        for format in [SampleFormat::S24Le, SampleFormat::S24Be] {
+            let decoded = decode_samples(&encode_samples(&samples, &format), &format);
+            assert!(samples.iter().zip(&decoded).all(|(a, b)| (a - b).abs() < 2.0 / S24_SCALE), "{:?}", decoded);
+        }
+    }
+}
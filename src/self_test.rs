@@ -0,0 +1,139 @@
+use crate::config::{MeasurementConfig, SensorConfig};
+use crate::metrics::SensorMetrics;
+use crate::sample_decode::encode_samples;
+use crate::signature_detector::{wall_clock_ns, SignatureDetector, SignatureEvent};
+use crate::signature_store::SignatureStore;
+use crate::tone_detector::ToneMatcher;
+use crate::units::Millis;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Synthetic measurement: a 1kHz reference tone is the only thing it detects,
+/// so the detector and matcher agree on the signature without real traffic
+const SELF_TEST_MEASUREMENT: &str = r#"
+name: "self_test"
+enabled: true
+signature_rules:
+  stream_filter: ""
+  sampling_rate: 1
+  audio_criteria:
+    min_duration_ms: 0
+    energy_threshold: 0.0
+    vad_mode: !Tone { frequency_hz: 1000.0 }
+    frequency_range: null
+metadata_extraction:
+  header_offset: 0
+  id_patterns: []
+  protocol: !Binary { field_map: [] }
+correlation:
+  signature_ttl_seconds: 60
+  max_active_signatures: 1
+  grouping_key: "interval_id"
+metric_labels: []
+"#;
+
+/// A match slower than this in-process means something is badly wrong
+const MAX_PLAUSIBLE_LATENCY: Duration = Duration::from_secs(1);
+
+/// Push a synthetic stream through detector -> signature broadcast -> store ->
+/// matcher using this deployment's seed and channel settings. Returns the
+/// observed latency, or which stage failed.
+pub async fn run(config: &SensorConfig, metrics: &SensorMetrics) -> Result<Duration, String> {
+    let measurement: MeasurementConfig = serde_yaml::from_str(SELF_TEST_MEASUREMENT)
+        .map_err(|e| format!("self-test measurement: {}", e))?;
+    let packets = synthetic_stream(&measurement);
+    
+    // Source side
+    let mut detector = SignatureDetector::new(measurement.clone(), config.hash_seed, metrics);
+    let event = packets.iter()
+        .find_map(|packet| detector.process_packet(packet, wall_clock_ns()).transpose())
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .ok_or("detector produced no signature for the reference tone")?;
+    let expected_hash = event.signature.hash;
+    
+    // Transport: same channel type and capacity as the live signature broadcast
+    let (sig_tx, mut sig_rx) = broadcast::channel::<SignatureEvent>(config.signature_channel_capacity.max(1));
+    sig_tx.send(event).map_err(|_| "signature broadcast has no receivers")?;
+    let received = sig_rx.recv().await.map_err(|e| format!("signature broadcast: {}", e))?;
+    if received.signature.seed != config.hash_seed {
+        return Err("signature arrived with a foreign hash seed".to_string());
+    }
+    
+    let store = SignatureStore::new();
+    store.insert(received);
+    
+    // Relay side: the same audio seen again downstream
+    let mut matcher = ToneMatcher::new(std::slice::from_ref(&measurement));
+    let matched = packets.iter()
+        .find_map(|packet| matcher.observe("self-test-relay", packet, &store, config.hash_seed).pop())
+        .ok_or("matcher never found the signature in the relayed stream")?;
+    
+    if matched.signature.hash != expected_hash {
+        return Err(format!("matched hash {:016x}, expected {:016x}", matched.signature.hash, expected_hash));
+    }
+    let latency = wall_clock_ns()
+        .checked_sub(matched.captured_at_ns)
+        .map(Duration::from_nanos)
+        .ok_or("match observed before its signature was captured")?;
+    if latency > MAX_PLAUSIBLE_LATENCY {
+        return Err(format!("implausible in-process latency {}", Millis(latency)));
+    }
+    if !store.is_empty() {
+        return Err("matched signature was not removed from the store".to_string());
+    }
+    
+    Ok(latency)
+}
+
+/// 200ms of silence then 500ms of tone, in 20ms packets of the measurement's format
+fn synthetic_stream(measurement: &MeasurementConfig) -> Vec<Vec<u8>> {
+    let criteria = &measurement.signature_rules.audio_criteria;
+    let rate = criteria.sample_rate as usize;
+    let per_packet = rate / 50;
+    
+    let samples: Vec<f32> = (0..rate * 7 / 10)
+        .map(|i| {
+            if i < rate / 5 {
+                0.0
+            } else {
+                0.5 * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / rate as f32).sin()
+            }
+        })
+        .collect();
+    
+    samples.chunks(per_packet)
+        .map(|chunk| encode_samples(chunk, &criteria.sample_format))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::test_metrics;
+    
+    fn config(hash_seed: u64) -> SensorConfig {
+        let mut config: SensorConfig = serde_yaml::from_str("measurements: []").unwrap();
+        config.hash_seed = hash_seed;
+        config
+    }
+    
+    #[tokio::test]
+    async fn pipeline_matches_the_reference_tone() {
+        for seed in [0, 0x5eed] {
+            let latency = run(&config(seed), &test_metrics()).await.unwrap();
+            assert!(latency < MAX_PLAUSIBLE_LATENCY);
+        }
+    }
+    
+    #[test]
+    fn synthetic_stream_is_silence_then_tone() {
+        let measurement: MeasurementConfig = serde_yaml::from_str(SELF_TEST_MEASUREMENT).unwrap();
+        let packets = synthetic_stream(&measurement);
+        
+        // 700ms in 20ms packets, the first 200ms silent
+        assert_eq!(packets.len(), 35);
+        assert!(packets[..10].iter().all(|p| p.iter().all(|&b| b == 0)));
+        assert!(packets[10..].iter().all(|p| p.iter().any(|&b| b != 0)));
+    }
+}
@@ -1,53 +1,489 @@
-use crate::config::{MeasurementConfig, VadMode};
+use crate::config::{
+    AudioFormat, BufferFullPolicy, CorrelationConfig, IdEncoding, MeasurementConfig, MissingMetadataPolicy,
+    PayloadEncryption, VadMode,
+};
+use crate::metrics::SensorMetrics;
+use crate::fingerprint::{self, Fingerprinter};
+use crate::rtp;
+use crate::sample_decode::{bandpass, decode_samples, downmix};
+use crate::tone_detector::{self, ToneOnsetDetector};
+use crate::windowing;
+use std::borrow::Cow;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// FFT frame length for spectral analysis
+const SPECTRAL_FRAME: usize = 256;
+
+/// Share of spectral energy that must fall inside frequency_range to count as speech
+const SPEECH_BAND_RATIO: f32 = 0.7;
+
+/// Packet gap that ends a talkspurt (silence suppression) for timing-only signatures
+const TALKSPURT_GAP_NS: u64 = 100_000_000;
+
+/// Payloads shorter than this are too small to judge randomness from
+const MIN_ENTROPY_SAMPLE: usize = 64;
+
+/// How far below a random payload's expected entropy still counts as random.
+/// Companded speech sits well under; sustained loud noise can get close,
+/// which is what the smoothing below is for.
+const ENTROPY_MARGIN_BITS: f32 = 0.35;
+
+/// Weight of each payload in the running "looks encrypted" score
+const RANDOM_SCORE_WEIGHT: f32 = 0.05;
+
+/// Sampled packets the audio buffer holds before buffer_full applies
+const BUFFER_CAPACITY: usize = 50;
+
+/// Hard ceiling for grow-to-duration, however long min_duration_ms is
+const MAX_GROWN_BUFFER: usize = 1000;
 
 /// Stateful detector that processes audio packets and finds signature points
 pub struct SignatureDetector {
     config: MeasurementConfig,
+    hash_seed: u64,
     audio_buffer: VecDeque<Vec<u8>>,
     packet_counter: u32,
+    current_interval: Option<String>,
+    /// Ids gathered across the current interval's packets
+    metadata: PacketMetadata,
+    /// Packets left before this stream/interval may emit signatures
+    warmup_remaining: u32,
+    /// correlation.interval_id_allowlist, compiled
+    interval_allowlist: Option<regex::Regex>,
+    tone: Option<ToneOnsetDetector>,
+    /// Decode path for buffered audio; follows the RTP payload type when parsing RTP
+    format: AudioFormat,
+    /// Trailing partial frame of the last payload, completed by the next one
+    frame_carry: Vec<u8>,
+    /// Every packet's audio, sampled for VAD or not, for the content fingerprint
+    fingerprinter: Fingerprinter,
+    fingerprint_frames: usize,
+    /// Capture time of the previous packet, for talkspurt gaps
+    last_captured_ns: Option<u64>,
+    /// Detect mode: smoothed share of random-looking payloads, and the verdict
+    random_score: f32,
+    encrypted: bool,
+    encrypted_payloads: prometheus::IntCounter,
+    misaligned: prometheus::IntCounter,
+    packets_sampled: prometheus::IntCounter,
+    missing_metadata: prometheus::IntCounter,
+    processing_time: prometheus::Histogram,
+    buffer_chunks: prometheus::IntGauge,
+    buffer_bytes: prometheus::IntGauge,
+    /// Labeled per id_type as ids turn up
+    metadata_conflicts: prometheus::IntCounterVec,
+    id_out_of_bounds: prometheus::IntCounterVec,
+    /// (chunks, bytes) last added to the shared buffer gauges
+    reported_buffer: (i64, i64),
 }
 
 impl SignatureDetector {
-    pub fn new(config: MeasurementConfig) -> Self {
+    pub fn new(config: MeasurementConfig, hash_seed: u64, metrics: &SensorMetrics) -> Self {
+        let criteria = &config.signature_rules.audio_criteria;
+        let tone = match criteria.vad_mode {
+            VadMode::Tone { frequency_hz, threshold } => {
+                Some(ToneOnsetDetector::new(frequency_hz, criteria.sample_rate, threshold))
+            }
+            _ => None,
+        };
+        let format = AudioFormat {
+            sample_format: criteria.sample_format.clone(),
+            sample_rate: criteria.sample_rate,
+            channels: 1,
+        };
+        let fingerprint_frames = fingerprint::window_frames(criteria.min_duration_ms);
+        let fingerprinter = Fingerprinter::new(criteria.sample_rate, fingerprint_frames);
+        let warmup_remaining = config.signature_rules.warmup_packets;
+        // An invalid pattern is reported by --validate; measure everything rather than nothing
+        let interval_allowlist = config.correlation.interval_id_allowlist.as_deref()
+            .and_then(|pattern| regex::Regex::new(pattern).ok());
+        let misaligned = metrics.misaligned_payloads.with_label_values(&[&config.name]);
+        let encrypted_payloads = metrics.encrypted_payloads.with_label_values(&[&config.name]);
+        let encrypted = matches!(criteria.encryption, PayloadEncryption::Srtp);
+        let packets_sampled = metrics.packets_sampled.with_label_values(&[&config.name]);
+        let missing_metadata = metrics.signatures_missing_metadata.with_label_values(&[&config.name]);
+        let processing_time = metrics.packet_processing_seconds.with_label_values(&[&config.name]);
+        let buffer_chunks = metrics.audio_buffer_chunks.with_label_values(&[&config.name]);
+        let buffer_bytes = metrics.audio_buffer_bytes.with_label_values(&[&config.name]);
+        
         Self {
             config,
+            hash_seed,
             audio_buffer: VecDeque::with_capacity(100),
             packet_counter: 0,
+            current_interval: None,
+            metadata: PacketMetadata::default(),
+            warmup_remaining,
+            interval_allowlist,
+            tone,
+            format,
+            frame_carry: Vec::new(),
+            fingerprinter,
+            fingerprint_frames,
+            last_captured_ns: None,
+            random_score: 0.0,
+            encrypted,
+            encrypted_payloads,
+            misaligned,
+            packets_sampled,
+            missing_metadata,
+            processing_time,
+            buffer_chunks,
+            buffer_bytes,
+            metadata_conflicts: metrics.metadata_conflicts.clone(),
+            id_out_of_bounds: metrics.id_out_of_bounds.clone(),
+            reported_buffer: (0, 0),
+        }
+    }
+    
+    /// Process a packet and potentially generate a signature.
+    /// `captured_at_ns` is the packet's wall-clock capture time (Pixie `time_`),
+    /// before any queueing. A signature missing grouping ids is passed, dropped
+    /// or returned as an error according to missing_metadata_policy.
+    pub fn process_packet(
+        &mut self,
+        payload: &[u8],
+        captured_at_ns: u64,
+    ) -> Result<Option<SignatureEvent>, DetectorError> {
+        let Some(event) = self.detect(payload, captured_at_ns) else {
+            return Ok(None);
+        };
+        
+        let missing: Vec<String> = self.config.correlation.grouping_key
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !event.metadata.ids.contains_key(*field))
+            .map(str::to_string)
+            .collect();
+        if missing.is_empty() {
+            return Ok(Some(event));
+        }
+        
+        self.missing_metadata.inc();
+        match self.config.metadata_extraction.missing_metadata_policy {
+            MissingMetadataPolicy::EmitAnyway => Ok(Some(event)),
+            MissingMetadataPolicy::Skip => Ok(None),
+            MissingMetadataPolicy::Error => Err(DetectorError::MissingMetadata {
+                measurement: self.config.name.clone(),
+                missing,
+            }),
         }
     }
     
-    /// Process a packet and potentially generate a signature
-    pub fn process_packet(&mut self, payload: &[u8]) -> Option<SignatureEvent> {
+    /// Detection alone, ignoring missing_metadata_policy: for calibrating VAD
+    /// on audio that carries no ids at all (WAV files)
+    pub fn detect(&mut self, payload: &[u8], captured_at_ns: u64) -> Option<SignatureEvent> {
+        // Every packet, sampled or not, may carry ids the signature needs
+        let metadata = self.accumulate_metadata(payload);
+        
+        // Not a focused interval (or not known yet): no audio state, no signatures
+        if let Some(allowlist) = &self.interval_allowlist {
+            if !self.current_interval.as_deref().is_some_and(|id| allowlist.is_match(id)) {
+                return None;
+            }
+        }
+        
+        // Still counts toward state below; only emission is held back
+        let warming_up = self.warmup_remaining > 0;
+        self.warmup_remaining = self.warmup_remaining.saturating_sub(1);
+        
+        // Strip transport framing; unknown RTP payload types carry nothing we can decode
+        let (audio, format) = rtp::audio_payload(payload, &self.config)?;
+        if format != self.format {
+            self.switch_format(format);
+        }
+        // Every payload, sampled or not, moves the stream's frame boundary
+        let audio = self.align_frames(audio);
+        let gap_ns = self.last_captured_ns
+            .replace(captured_at_ns)
+            .map(|previous| captured_at_ns.saturating_sub(previous));
+        
+        // Ciphertext decodes to noise; only its timing means anything
+        if self.update_encryption(&audio) {
+            self.encrypted_payloads.inc();
+            let event = self.process_encrypted(payload, metadata, captured_at_ns, gap_ns);
+            return event.filter(|_| !warming_up);
+        }
+        
+        // Onset timing needs every sample, so tone mode bypasses packet sampling
+        if self.tone.is_some() {
+            self.packets_sampled.inc();
+            let started = Instant::now();
+            let event = self.process_tone(&audio, metadata, captured_at_ns);
+            self.processing_time.observe(started.elapsed().as_secs_f64());
+            return event.filter(|_| !warming_up);
+        }
+        
+        // The fingerprint covers contiguous audio; sampling only thins out VAD
+        let samples = self.decode(&audio);
+        self.fingerprinter.push(&samples);
+        
         self.packet_counter += 1;
         
         // Sample according to configured rate
         if self.packet_counter % self.config.signature_rules.sampling_rate != 0 {
             return None;
         }
+        self.packets_sampled.inc();
         
-        // Extract metadata first (it's always there, even if we don't use this packet)
-        let metadata = self.extract_metadata(payload);
-        
+        // Only sampled packets are timed; the rest cost next to nothing
+        let started = Instant::now();
+        let event = self.analyze(audio.into_owned(), metadata, captured_at_ns, warming_up);
+        self.processing_time.observe(started.elapsed().as_secs_f64());
+        event
+    }
+    
+    /// Buffer a sampled packet's audio and run VAD over the buffer
+    fn analyze(
+        &mut self,
+        audio: Vec<u8>,
+        metadata: PacketMetadata,
+        captured_at_ns: u64,
+        warming_up: bool,
+    ) -> Option<SignatureEvent> {
         // Buffer audio for duration analysis
-        self.audio_buffer.push_back(payload.to_vec());
-        if self.audio_buffer.len() > 50 {
+        self.audio_buffer.push_back(audio);
+        let policy = self.config.signature_rules.buffer_full.clone();
+        match policy {
+            BufferFullPolicy::FifoDrop => {
+                if self.audio_buffer.len() > BUFFER_CAPACITY {
+                    self.audio_buffer.pop_front();
+                }
+            }
+            BufferFullPolicy::EmitOnFull => {}
+            BufferFullPolicy::GrowToDuration => self.trim_to_duration(),
+        }
+        self.update_buffer_gauges();
+        
+        let signature = if policy == BufferFullPolicy::EmitOnFull {
+            // Only complete windows are analysed, each starting afresh
+            if self.audio_buffer.len() < BUFFER_CAPACITY {
+                return None;
+            }
+            let signature = (!warming_up && self.is_signature_worthy()).then(|| self.generate_signature());
+            self.audio_buffer.clear();
+            self.update_buffer_gauges();
+            signature??
+        } else if !warming_up && self.is_signature_worthy() {
+            // Check if this is a signature-worthy moment
+            self.generate_signature()?
+        } else {
+            return None;
+        };
+        
+        Some(SignatureEvent {
+            signature,
+            metadata,
+            captured_at_ns,
+            generated_at_ns: wall_clock_ns(),
+            measurement_name: self.config.name.clone(),
+            source_pod: None,
+        })
+    }
+    
+    /// Past capacity, drop the oldest packets only while what remains still
+    /// covers min_duration_ms (up to MAX_GROWN_BUFFER packets)
+    fn trim_to_duration(&mut self) {
+        let needed_us = self.config.signature_rules.audio_criteria.min_duration_ms as u64 * 1000;
+        while self.audio_buffer.len() > BUFFER_CAPACITY {
+            let spare = self.audio_buffer.len() > MAX_GROWN_BUFFER
+                || self.buffered_us() - self.chunk_us(&self.audio_buffer[0]) >= needed_us;
+            if !spare {
+                break;
+            }
             self.audio_buffer.pop_front();
         }
+    }
+    
+    /// Emit a signature positioned at the onset of the reference tone
+    fn process_tone(&mut self, audio: &[u8], metadata: PacketMetadata, captured_at_ns: u64) -> Option<SignatureEvent> {
+        let VadMode::Tone { frequency_hz, .. } = self.config.signature_rules.audio_criteria.vad_mode else {
+            return None;
+        };
         
-        // Check if this is a signature-worthy moment
-        if self.is_signature_worthy() {
-            let signature = self.generate_signature();
-            
-            return Some(SignatureEvent {
-                signature,
-                metadata,
-                timestamp: std::time::Instant::now(),
-                measurement_name: self.config.name.clone(),
-            });
+        let samples = self.decode(audio);
+        let (onset, strength) = self.tone.as_mut()?.feed(&samples)?;
+        
+        Some(SignatureEvent {
+            signature: AudioSignature {
+                hash: tone_detector::tone_signature_hash(&self.config.name, frequency_hz, self.hash_seed),
+                duration_ms: 0,
+                seed: self.hash_seed,
+                onset_sample: Some(onset),
+                confidence: strength.min(1.0),
+                fingerprint: Vec::new(),
+            },
+            metadata,
+            captured_at_ns,
+            generated_at_ns: wall_clock_ns(),
+            measurement_name: self.config.name.clone(),
+            source_pod: None,
+        })
+    }
+    
+    /// Srtp: always encrypted. Detect: flips once most recent payloads look
+    /// random and back once most don't, so one odd packet can't flap it.
+    fn update_encryption(&mut self, audio: &[u8]) -> bool {
+        if !matches!(self.config.signature_rules.audio_criteria.encryption, PayloadEncryption::Detect) {
+            return self.encrypted;
+        }
+        
+        let random = if looks_random(audio) { 1.0 } else { 0.0 };
+        self.random_score += (random - self.random_score) * RANDOM_SCORE_WEIGHT;
+        
+        let was_encrypted = self.encrypted;
+        self.encrypted = if was_encrypted { self.random_score > 0.5 } else { self.random_score > 0.8 };
+        if self.encrypted != was_encrypted {
+            self.audio_buffer.clear();
+            self.fingerprinter.clear();
+            self.update_buffer_gauges();
+            tracing::info!(
+                measurement = %self.config.name,
+                encrypted = self.encrypted,
+                "payload encryption detection changed; {}",
+                if self.encrypted { "correlating on timing only" } else { "resuming VAD" }
+            );
+        }
+        self.encrypted
+    }
+    
+    /// Timing-only signature: the first packet after a silence-suppression gap
+    /// starts a talkspurt. Relays forward ciphertext byte-for-byte, so the
+    /// packet's own hash (what the matcher computes) identifies it downstream.
+    fn process_encrypted(
+        &self,
+        packet: &[u8],
+        metadata: PacketMetadata,
+        captured_at_ns: u64,
+        gap_ns: Option<u64>,
+    ) -> Option<SignatureEvent> {
+        if gap_ns.is_some_and(|gap| gap < TALKSPURT_GAP_NS) {
+            return None;
+        }
+        
+        Some(SignatureEvent {
+            signature: AudioSignature {
+                hash: xxhash_rust::xxh3::xxh3_64_with_seed(packet, self.hash_seed),
+                duration_ms: 0,
+                seed: self.hash_seed,
+                onset_sample: None,
+                confidence: 1.0,
+                fingerprint: packet.to_vec(),
+            },
+            metadata,
+            captured_at_ns,
+            generated_at_ns: wall_clock_ns(),
+            measurement_name: self.config.name.clone(),
+            source_pod: None,
+        })
+    }
+    
+    /// Buffered audio in another codec/rate can't be analysed together with the
+    /// new packets, so a payload type change starts over
+    fn switch_format(&mut self, format: AudioFormat) {
+        if let VadMode::Tone { frequency_hz, threshold } = self.config.signature_rules.audio_criteria.vad_mode {
+            self.tone = Some(ToneOnsetDetector::new(frequency_hz, format.sample_rate, threshold));
+        }
+        self.audio_buffer.clear();
+        self.update_buffer_gauges();
+        self.frame_carry.clear();
+        self.fingerprinter = Fingerprinter::new(format.sample_rate, self.fingerprint_frames);
+        self.format = format;
+    }
+    
+    /// Whole sample frames of `audio`. Realign mode prefixes the previous
+    /// payload's partial frame and holds back this one's, so a 99-byte chunk
+    /// of 16-bit audio doesn't shift every sample after it.
+    fn align_frames<'a>(&mut self, audio: &'a [u8]) -> Cow<'a, [u8]> {
+        let frame = self.format.sample_format.bytes_per_sample() * self.format.channels.max(1) as usize;
+        let aligned = audio.len().is_multiple_of(frame);
+        if !aligned {
+            if self.misaligned.get() == 0 {
+                tracing::warn!(
+                    measurement = %self.config.name,
+                    payload_len = audio.len(),
+                    frame_bytes = frame,
+                    "audio payload isn't frame-aligned; further occurrences only counted"
+                );
+            }
+            self.misaligned.inc();
         }
         
-        None
+        let alignment = &self.config.signature_rules.audio_criteria.frame_alignment;
+        fingerprint::align_frames(&mut self.frame_carry, audio, frame, alignment)
+    }
+    
+    /// Mono samples of one buffered chunk in the current format
+    fn decode(&self, chunk: &[u8]) -> Vec<f32> {
+        downmix(decode_samples(chunk, &self.format.sample_format), self.format.channels)
+    }
+    
+    /// The whole buffer as mono samples for energy and zero-crossing VAD,
+    /// band-limited to frequency_range when bandpass is set
+    fn vad_samples(&self) -> Vec<f32> {
+        let criteria = &self.config.signature_rules.audio_criteria;
+        let samples: Vec<f32> = self.audio_buffer.iter()
+            .flat_map(|chunk| self.decode(chunk))
+            .collect();
+        
+        if !criteria.bandpass {
+            return samples;
+        }
+        let band = criteria.frequency_range.unwrap_or((300.0, 3400.0));
+        bandpass(&samples, self.format.sample_rate, band)
+    }
+    
+    /// Move this detector's contribution to the measurement-wide buffer
+    /// gauges to the current occupancy. Many detectors (streams, shards)
+    /// share the gauges, so each adds its change rather than setting them.
+    fn update_buffer_gauges(&mut self) {
+        let chunks = self.audio_buffer.len() as i64;
+        let bytes = self.audio_buffer.iter().map(Vec::len).sum::<usize>() as i64;
+        let (reported_chunks, reported_bytes) = std::mem::replace(&mut self.reported_buffer, (chunks, bytes));
+        self.buffer_chunks.add(chunks - reported_chunks);
+        self.buffer_bytes.add(bytes - reported_bytes);
+    }
+    
+    /// Merge this packet's ids into the current interval's metadata, so ids
+    /// split across packets (call_id here, segment_id there) all reach the
+    /// signature. A new interval_id starts fresh and, with reset_per_interval,
+    /// also a clean detection window. On conflicting values the first wins.
+    fn accumulate_metadata(&mut self, payload: &[u8]) -> PacketMetadata {
+        let packet = self.extract_metadata(payload);
+        
+        if let Some(interval_id) = packet.ids.get("interval_id") {
+            if self.current_interval.as_ref() != Some(interval_id) {
+                // Ids seen before the first interval_id belong to it
+                if self.current_interval.is_some() {
+                    self.metadata = PacketMetadata::default();
+                    self.warmup_remaining = self.config.signature_rules.warmup_packets;
+                }
+                if self.config.signature_rules.reset_per_interval {
+                    self.audio_buffer.clear();
+                    self.fingerprinter.clear();
+                    self.update_buffer_gauges();
+                    self.packet_counter = 0;
+                }
+                self.current_interval = Some(interval_id.clone());
+            }
+        }
+        
+        for (id_type, value) in packet.ids {
+            match self.metadata.ids.get(&id_type) {
+                None => {
+                    self.metadata.ids.insert(id_type, value);
+                }
+                Some(first) if *first != value => {
+                    self.metadata_conflicts
+                        .with_label_values(&[&self.config.name, &id_type])
+                        .inc();
+                }
+                Some(_) => {}
+            }
+        }
+        
+        self.metadata.clone()
     }
     
     fn extract_metadata(&self, payload: &[u8]) -> PacketMetadata {
@@ -59,22 +495,57 @@ impl SignatureDetector {
             return metadata;
         }
         
-        // Try each ID pattern
+        // Try each ID pattern in priority order; later ones are fallbacks
         for pattern in &self.config.metadata_extraction.id_patterns {
-            if pattern.pattern.starts_with("\\x") {
-                // Binary pattern matching
-                if let Some(pos) = self.find_bytes(payload, &pattern.pattern) {
-                    let id_start = pos + pattern.value_offset as usize;
-                    let id_end = id_start + pattern.value_length;
-                    if id_end <= payload.len() {
-                        let id_bytes = &payload[id_start..id_end];
-                        let id = String::from_utf8_lossy(id_bytes).to_string();
-                        metadata.ids.insert(pattern.id_type.clone(), id);
+            if metadata.ids.contains_key(&pattern.id_type) {
+                continue;
+            }
+            
+            let binary = pattern.pattern.starts_with("\\x");
+            let search_start = pattern.search_start.unwrap_or(if binary { 0 } else { envelope_start });
+            let search_end = pattern.search_end.map_or(payload.len(), |end| end.min(payload.len()));
+            let Some(region) = payload.get(search_start..search_end) else {
+                continue;
+            };
+            
+            if binary {
+                // Binary pattern matching; the marker must lie in the region,
+                // the value is located relative to it anywhere in the payload
+                if let Some(pos) = self.find_bytes(region, &pattern.pattern).map(|at| search_start + at) {
+                    // Negative offsets point back before the marker
+                    let id_bytes = (pos as i64)
+                        .checked_add(pattern.value_offset as i64)
+                        .and_then(|start| usize::try_from(start).ok())
+                        .and_then(|start| payload.get(start..start.checked_add(pattern.value_length)?));
+                    
+                    match id_bytes {
+                        Some(id_bytes) => {
+                            if let Some(id) = pattern.encoding.decode(id_bytes) {
+                                metadata.ids.insert(pattern.id_type.clone(), id);
+                            }
+                        }
+                        None => {
+                            // Marker found but the value isn't there: misconfigured
+                            // offset/length rather than an absent marker
+                            tracing::debug!(
+                                measurement = %self.config.name,
+                                pattern = %pattern.pattern,
+                                id_type = %pattern.id_type,
+                                marker_at = pos,
+                                value_offset = pattern.value_offset,
+                                value_length = pattern.value_length,
+                                payload_len = payload.len(),
+                                "id value out of payload bounds"
+                            );
+                            self.id_out_of_bounds
+                                .with_label_values(&[&self.config.name, &pattern.id_type])
+                                .inc();
+                        }
                     }
                 }
             } else {
                 // Regex pattern (for JSON, etc)
-                let text = String::from_utf8_lossy(&payload[envelope_start..]);
+                let text = String::from_utf8_lossy(region);
                 if let Ok(re) = regex::Regex::new(&pattern.pattern) {
                     if let Some(cap) = re.captures(&text) {
                         if let Some(id) = cap.get(1) {
@@ -89,37 +560,80 @@ impl SignatureDetector {
     }
     
     fn is_signature_worthy(&self) -> bool {
+        if !self.has_enough_audio() {
+            return false;
+        }
+        
         // Implement VAD logic based on configured mode
         match &self.config.signature_rules.audio_criteria.vad_mode {
             VadMode::Energy => self.check_energy_threshold(),
             VadMode::ZeroCrossing => self.check_zero_crossing_rate(),
             VadMode::Spectral => self.check_spectral_features(),
             VadMode::ML { model_path } => self.run_ml_vad(model_path),
+            // Handled per packet in process_tone
+            VadMode::Tone { .. } => false,
         }
     }
     
+    /// Whether the buffer holds min_buffered_ms (or min_duration_ms) of audio
+    fn has_enough_audio(&self) -> bool {
+        let criteria = &self.config.signature_rules.audio_criteria;
+        let floor_ms = criteria.min_buffered_ms.unwrap_or(criteria.min_duration_ms) as u64;
+        self.buffered_us() >= floor_ms * 1000
+    }
+    
+    /// Duration of the buffered audio in µs
+    fn buffered_us(&self) -> u64 {
+        self.audio_buffer.iter().map(|chunk| self.chunk_us(chunk)).sum()
+    }
+    
+    /// Duration of one buffered chunk in µs, in the current format
+    fn chunk_us(&self, chunk: &[u8]) -> u64 {
+        let frame_bytes = self.format.sample_format.bytes_per_sample() * self.format.channels.max(1) as usize;
+        (chunk.len() / frame_bytes) as u64 * 1_000_000 / self.format.sample_rate.max(1) as u64
+    }
+    
+    /// How strongly the configured VAD fired, 0.0..=1.0
+    fn vad_confidence(&self) -> f32 {
+        let confidence = match &self.config.signature_rules.audio_criteria.vad_mode {
+            VadMode::Spectral => self.speech_band_ratio().unwrap_or(0.0),
+            // No model output yet; fall back to signal strength like the other modes
+            VadMode::Energy | VadMode::ZeroCrossing | VadMode::ML { .. } => self.buffer_rms().unwrap_or(0.0),
+            VadMode::Tone { .. } => 1.0,
+        };
+        confidence.clamp(0.0, 1.0)
+    }
+    
     fn check_energy_threshold(&self) -> bool {
-        // Simple RMS energy calculation
-        let total_samples: usize = self.audio_buffer.iter()
-            .map(|chunk| chunk.len() / 2)  // Assuming 16-bit audio
-            .sum();
-            
-        if total_samples == 0 {
-            return false;
+        self.buffer_rms()
+            .is_some_and(|rms| rms > self.config.signature_rules.audio_criteria.energy_threshold)
+    }
+    
+    /// RMS of the buffered audio as normalized samples (full-scale sine ~0.707),
+    /// on the same scale as chunk_energy. With rms_window_ms set, the loudest
+    /// sliding window's RMS, so a short burst isn't diluted by the silence around it.
+    fn buffer_rms(&self) -> Option<f32> {
+        let criteria = &self.config.signature_rules.audio_criteria;
+        let samples = self.vad_samples();
+        
+        if samples.is_empty() {
+            return None;
         }
         
-        let mut energy = 0.0;
-        for chunk in &self.audio_buffer {
-            for i in (0..chunk.len()).step_by(2) {
-                if i + 1 < chunk.len() {
-                    let sample = i16::from_le_bytes([chunk[i], chunk[i + 1]]);
-                    energy += (sample as f32).powi(2);
-                }
-            }
+        let window = criteria.rms_window_ms
+            .map(|ms| (self.format.sample_rate as usize * ms as usize / 1000).max(1))
+            .unwrap_or(samples.len())
+            .min(samples.len());
+        
+        // Running sum of squares across the slide
+        let mut energy: f32 = samples[..window].iter().map(|s| s * s).sum();
+        let mut loudest = energy;
+        for i in window..samples.len() {
+            energy += samples[i] * samples[i] - samples[i - window] * samples[i - window];
+            loudest = loudest.max(energy);
         }
         
-        let rms = (energy / total_samples as f32).sqrt() / 32768.0;  // Normalize
-        rms > self.config.signature_rules.audio_criteria.energy_threshold
+        Some((loudest.max(0.0) / window as f32).sqrt())
     }
     
     fn check_zero_crossing_rate(&self) -> bool {
@@ -127,17 +641,12 @@ impl SignatureDetector {
         let mut crossings = 0;
         let mut prev_sign = 0i8;
         
-        for chunk in &self.audio_buffer {
-            for i in (0..chunk.len()).step_by(2) {
-                if i + 1 < chunk.len() {
-                    let sample = i16::from_le_bytes([chunk[i], chunk[i + 1]]);
-                    let sign = sample.signum() as i8;
-                    if prev_sign != 0 && sign != prev_sign {
-                        crossings += 1;
-                    }
-                    prev_sign = sign;
-                }
+        for sample in self.vad_samples() {
+            let sign = if sample > 0.0 { 1 } else if sample < 0.0 { -1 } else { 0 };
+            if prev_sign != 0 && sign != prev_sign {
+                crossings += 1;
             }
+            prev_sign = sign;
         }
         
         // Speech typically has 10-30 crossings per 10ms
@@ -146,9 +655,40 @@ impl SignatureDetector {
     }
     
     fn check_spectral_features(&self) -> bool {
-        // TODO: Implement FFT-based detection
-        // Would check for formant frequencies typical of speech
-        false
+        self.speech_band_ratio().is_some_and(|ratio| ratio > SPEECH_BAND_RATIO)
+            && self.check_energy_threshold()
+    }
+    
+    /// Share of buffered spectral energy inside frequency_range
+    fn speech_band_ratio(&self) -> Option<f32> {
+        // Speech concentrates its energy in the formant band (frequency_range);
+        // broadband noise and tones outside it don't
+        let criteria = &self.config.signature_rules.audio_criteria;
+        let (low, high) = criteria.frequency_range.unwrap_or((300.0, 3400.0));
+        let bin_hz = self.format.sample_rate as f32 / SPECTRAL_FRAME as f32;
+        
+        let samples: Vec<f32> = self.audio_buffer.iter()
+            .flat_map(|chunk| self.decode(chunk))
+            .collect();
+        
+        let mut in_band = 0.0;
+        let mut total = 0.0;
+        for frame in samples.chunks_exact(SPECTRAL_FRAME) {
+            let spectrum = windowing::power_spectrum(frame, &criteria.window_function);
+            for (bin, power) in spectrum.iter().enumerate() {
+                let freq = bin as f32 * bin_hz;
+                total += power;
+                if freq >= low && freq <= high {
+                    in_band += power;
+                }
+            }
+        }
+        
+        if total == 0.0 {
+            return None;
+        }
+        
+        Some(in_band / total)
     }
     
     fn run_ml_vad(&self, model_path: &str) -> bool {
@@ -156,58 +696,619 @@ impl SignatureDetector {
         false
     }
     
-    fn generate_signature(&self) -> AudioSignature {
-        // Create a compact signature from the buffered audio
-        // Using perceptual hash or spectral fingerprint
-        
-        // For now, simple hash of energy profile
-        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
-        
-        // Hash energy values over time windows
-        for chunk in &self.audio_buffer {
-            let energy = self.chunk_energy(chunk);
-            hasher.update(&energy.to_le_bytes());
-        }
+    /// Energy profile of the newest min_duration_ms of contiguous audio (see
+    /// `Fingerprinter`); None until the stream has carried that much
+    fn generate_signature(&self) -> Option<AudioSignature> {
+        let fingerprint = self.fingerprinter.fingerprint(self.fingerprint_frames)?;
         
-        AudioSignature {
-            hash: hasher.digest(),
-            duration_ms: (self.audio_buffer.len() * 20) as u32,  // Assuming 20ms chunks
-        }
+        Some(AudioSignature {
+            hash: fingerprint::hash(&fingerprint, self.hash_seed),
+            duration_ms: (self.fingerprint_frames * fingerprint::FRAME_MS) as u32,
+            seed: self.hash_seed,
+            onset_sample: None,
+            confidence: self.vad_confidence(),
+            fingerprint,
+        })
     }
     
-    fn chunk_energy(&self, chunk: &[u8]) -> f32 {
-        let mut sum = 0.0;
-        for i in (0..chunk.len()).step_by(2) {
-            if i + 1 < chunk.len() {
-                let sample = i16::from_le_bytes([chunk[i], chunk[i + 1]]);
-                sum += (sample as f32).abs();
-            }
+    fn find_bytes(&self, haystack: &[u8], pattern: &str) -> Option<usize> {
+        let needle = parse_byte_pattern(pattern).ok()?;
+        haystack.windows(needle.len()).position(|window| window == needle.as_slice())
+    }
+}
+
+impl Drop for SignatureDetector {
+    /// An evicted stream's buffer no longer counts
+    fn drop(&mut self) {
+        self.buffer_chunks.sub(self.reported_buffer.0);
+        self.buffer_bytes.sub(self.reported_buffer.1);
+    }
+}
+
+impl IdEncoding {
+    /// Width in bytes for fixed-size integer encodings
+    pub fn fixed_width(&self) -> Option<usize> {
+        match self {
+            IdEncoding::Utf8 | IdEncoding::Hex => None,
+            IdEncoding::U32Be | IdEncoding::U32Le => Some(4),
+            IdEncoding::U64Be => Some(8),
         }
-        sum / (chunk.len() as f32 / 2.0)
     }
     
-    fn find_bytes(&self, haystack: &[u8], pattern: &str) -> Option<usize> {
-        // Convert \x00\x42 style pattern to bytes
-        // This is simplified - real impl would parse hex properly
-        None  // TODO
+    /// Render extracted ID bytes as a correlation key; None if the length
+    /// doesn't fit the encoding
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        if self.fixed_width().is_some_and(|width| width != bytes.len()) {
+            return None;
+        }
+        
+        Some(match self {
+            IdEncoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            IdEncoding::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            IdEncoding::U32Be => u32::from_be_bytes(bytes.try_into().ok()?).to_string(),
+            IdEncoding::U32Le => u32::from_le_bytes(bytes.try_into().ok()?).to_string(),
+            IdEncoding::U64Be => u64::from_be_bytes(bytes.try_into().ok()?).to_string(),
+        })
     }
 }
 
+/// Convert a `\x00\x42` style pattern to bytes
+pub fn parse_byte_pattern(pattern: &str) -> Result<Vec<u8>, String> {
+    let Some(rest) = pattern.strip_prefix("\\x") else {
+        return Err("byte pattern must start with \\x".to_string());
+    };
+    
+    rest.split("\\x")
+        .map(|hex| {
+            if hex.len() != 2 {
+                return Err(format!("expected two hex digits, got {:?}", hex));
+            }
+            u8::from_str_radix(hex, 16).map_err(|_| format!("invalid hex byte {:?}", hex))
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct SignatureEvent {
     pub signature: AudioSignature,
     pub metadata: PacketMetadata,
-    pub timestamp: std::time::Instant,
+    /// When the triggering audio was captured, wall-clock nanos since the epoch.
+    /// Same clock domain as Pixie row timestamps, so the matcher can subtract them.
+    pub captured_at_ns: u64,
+    /// When the signature was generated (after detection processing), wall-clock nanos
+    pub generated_at_ns: u64,
     pub measurement_name: String,
+    /// Pod whose traffic the signature was detected in, when known
+    pub source_pod: Option<String>,
+}
+
+impl SignatureEvent {
+    /// End-to-end latency from audio capture to a relay seeing it at
+    /// `observed_at_ns`. None when the relay saw it first: clock skew or a
+    /// misconfigured port, never a real latency.
+    pub fn latency_to(&self, observed_at_ns: u64) -> Option<Duration> {
+        observed_at_ns.checked_sub(self.captured_at_ns).map(Duration::from_nanos)
+    }
+}
+
+/// Byte entropy close to what uniformly random bytes of this length would
+/// show (which falls short of 8 bits for short payloads)
+fn looks_random(bytes: &[u8]) -> bool {
+    if bytes.len() < MIN_ENTROPY_SAMPLE {
+        return false;
+    }
+    
+    let mut counts = [0u32; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let n = bytes.len() as f32;
+    let entropy: f32 = counts.iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f32 / n;
+            -p * p.log2()
+        })
+        .sum();
+    
+    // Miller-Madow: expected shortfall of the plug-in estimate for 256 symbols
+    let expected = 8.0 - 255.0 / (2.0 * n * std::f32::consts::LN_2);
+    entropy >= expected - ENTROPY_MARGIN_BITS
+}
+
+/// Current wall-clock time as nanos since the epoch, the clock Pixie stamps rows with
+pub fn wall_clock_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Detection failures the supervising task decides how to handle
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum DetectorError {
+    /// A signature without its grouping ids, under missing_metadata_policy: error
+    #[error("measurement '{measurement}': signature missing required metadata {missing:?}")]
+    MissingMetadata {
+        measurement: String,
+        missing: Vec<String>,
+    },
+    /// A detector worker thread exited (panicked) with packets still coming
+    #[error("detector worker stopped")]
+    WorkerStopped,
 }
 
 #[derive(Debug, Clone)]
 pub struct AudioSignature {
     pub hash: u64,
     pub duration_ms: u32,
+    /// Deployment hash seed; signatures only match under the same seed
+    pub seed: u64,
+    /// Stream sample position of the tone onset (tone mode only)
+    pub onset_sample: Option<u64>,
+    /// How strongly the VAD fired (normalized RMS, in-band share or tone share), 0.0..=1.0
+    pub confidence: f32,
+    /// The bytes that were hashed (frame levels, or the packet when encrypted);
+    /// empty for tone signatures, whose hash is derived from config
+    pub fingerprint: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct PacketMetadata {
     pub ids: std::collections::HashMap<String, String>,
-}
\ No newline at end of file
+}
+
+impl PacketMetadata {
+    /// Correlation group for these ids: the grouping_key fields' values joined
+    /// with '/'. None if any field is missing, rather than mis-grouping.
+    pub fn grouping_key(&self, correlation: &CorrelationConfig) -> Option<String> {
+        correlation.grouping_key
+            .split(',')
+            .map(|field| self.ids.get(field.trim()).map(String::as_str))
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{test_measurement, SampleFormat};
+    use crate::metrics::test_metrics;
+    use crate::sample_decode::encode_samples;
+    
+    /// Measurement reading `iid=<id>;` at the start of each payload as its interval_id
+    fn tagged_measurement() -> MeasurementConfig {
+        let mut config = test_measurement();
+        config.metadata_extraction.id_patterns = vec![serde_yaml::from_str(
+            r#"{ pattern: 'iid=(\w+);', id_type: interval_id, value_offset: 0, value_length: 0 }"#,
+        ).unwrap()];
+        config
+    }
+    
+    /// 20ms packet: the interval tag, then silence
+    fn tagged(interval_id: &str) -> Vec<u8> {
+        let mut packet = format!("iid={};", interval_id).into_bytes();
+        packet.resize(320, 0);
+        packet
+    }
+    
+    /// 20ms packets of a steady 440Hz tone at `amplitude` of full scale
+    fn tone(amplitude: f32, packets: usize) -> Vec<Vec<u8>> {
+        let samples: Vec<f32> = (0..packets * 160)
+            .map(|i| (i as f32 / 8000.0 * 440.0 * std::f32::consts::TAU).sin() * amplitude)
+            .collect();
+        encode_samples(&samples, &SampleFormat::S16Le)
+            .chunks(320)
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+    
+    /// Confidence of the first signature `packets` produce
+    fn first_confidence(config: MeasurementConfig, packets: &[Vec<u8>]) -> Option<f32> {
+        let mut detector = SignatureDetector::new(config, 0, &test_metrics());
+        packets.iter()
+            .find_map(|packet| detector.detect(packet, 0))
+            .map(|event| event.signature.confidence)
+    }
+    
+    #[test]
+    fn buffer_gauges_sum_over_detectors() {
+        let metrics = test_metrics();
+        let config = test_measurement();
+        let chunks = metrics.audio_buffer_chunks.with_label_values(&[&config.name]);
+        let bytes = metrics.audio_buffer_bytes.with_label_values(&[&config.name]);
+        let silence = vec![0u8; 320];
+        
+        let mut first = SignatureDetector::new(config.clone(), 0, &metrics);
+        let mut second = SignatureDetector::new(config, 0, &metrics);
+        for at in 0..3 {
+            first.detect(&silence, at);
+        }
+        second.detect(&silence, 0);
+        assert_eq!(chunks.get(), 4);
+        assert_eq!(bytes.get(), 4 * 320);
+        
+        drop(first);
+        assert_eq!(chunks.get(), 1);
+        assert_eq!(bytes.get(), 320);
+        drop(second);
+        assert_eq!(chunks.get(), 0);
+    }
+    
+    #[test]
+    fn reset_per_interval_starts_a_clean_window() {
+        for (reset, expected) in [(false, 4), (true, 1)] {
+            let metrics = test_metrics();
+            let mut config = tagged_measurement();
+            config.signature_rules.reset_per_interval = reset;
+            let chunks = metrics.audio_buffer_chunks.with_label_values(&[&config.name]);
+            
+            let mut detector = SignatureDetector::new(config, 0, &metrics);
+            for interval_id in ["1", "1", "1", "2"] {
+                detector.detect(&tagged(interval_id), 0);
+            }
+            assert_eq!(chunks.get(), expected, "reset_per_interval: {}", reset);
+        }
+    }
+    
+    #[test]
+    fn latency_is_none_when_the_relay_saw_it_first() {
+        let event = SignatureEvent {
+            signature: AudioSignature {
+                hash: 1,
+                duration_ms: 200,
+                seed: 0,
+                onset_sample: None,
+                confidence: 1.0,
+                fingerprint: Vec::new(),
+            },
+            metadata: PacketMetadata::default(),
+            captured_at_ns: 5_000_000,
+            generated_at_ns: 9_000_000,
+            measurement_name: "test".to_string(),
+            source_pod: None,
+        };
+        
+        // Measured from capture, not from when the signature was generated
+        assert_eq!(event.latency_to(7_000_000), Some(Duration::from_millis(2)));
+        assert_eq!(event.latency_to(5_000_000), Some(Duration::ZERO));
+        assert_eq!(event.latency_to(4_999_999), None);
+    }
+    
+    #[test]
+    fn louder_audio_signs_with_more_confidence() {
+        let quiet = first_confidence(test_measurement(), &tone(0.1, 10)).unwrap();
+        let loud = first_confidence(test_measurement(), &tone(0.5, 10)).unwrap();
+        let clipped = first_confidence(test_measurement(), &tone(1.0, 10)).unwrap();
+        
+        // Energy VAD reports the buffer RMS (a sine's is amplitude / sqrt 2)
+        assert!((quiet - 0.0707).abs() < 0.005, "{}", quiet);
+        assert!(loud > quiet);
+        assert!(clipped <= 1.0);
+    }
+    
+    #[test]
+    fn rms_window_catches_a_burst_the_whole_buffer_dilutes() {
+        // 20ms at 0.028 RMS, then silence: 0.009 over the 200ms buffer
+        let mut packets = tone(0.04, 1);
+        packets.extend(std::iter::repeat_n(vec![0u8; 320], 19));
+        
+        assert_eq!(first_confidence(test_measurement(), &packets), None);
+        let mut windowed = test_measurement();
+        windowed.signature_rules.audio_criteria.rms_window_ms = Some(20);
+        assert!(first_confidence(windowed, &packets).is_some());
+    }
+    
+    #[test]
+    fn events_carry_the_capture_time_and_a_wall_clock_generation_time() {
+        let mut detector = SignatureDetector::new(test_measurement(), 0, &test_metrics());
+        let before = wall_clock_ns();
+        let event = tone(0.5, 10).iter()
+            .enumerate()
+            .find_map(|(i, packet)| detector.detect(packet, 1_000 + i as u64 * 20_000_000))
+            .unwrap();
+        
+        // The packet that completed the window, as Pixie stamped it
+        assert_eq!(event.captured_at_ns, 1_000 + 9 * 20_000_000);
+        assert!((before..=wall_clock_ns()).contains(&event.generated_at_ns));
+    }
+    
+    #[test]
+    fn only_sampled_packets_are_counted() {
+        let metrics = test_metrics();
+        let mut config = test_measurement();
+        config.signature_rules.sampling_rate = 3;
+        let sampled = metrics.packets_sampled.with_label_values(&[&config.name]);
+        
+        let mut detector = SignatureDetector::new(config, 0, &metrics);
+        for packet in tone(0.5, 9) {
+            detector.detect(&packet, 0);
+        }
+        assert_eq!(sampled.get(), 3);
+    }
+    
+    #[test]
+    fn id_encodings_render_or_reject_by_width() {
+        let bytes = [0x00, 0x00, 0x01, 0x02];
+        assert_eq!(IdEncoding::U32Be.decode(&bytes).as_deref(), Some("258"));
+        assert_eq!(IdEncoding::U32Le.decode(&bytes).as_deref(), Some("33619968"));
+        assert_eq!(IdEncoding::Hex.decode(&bytes).as_deref(), Some("00000102"));
+        assert_eq!(IdEncoding::Utf8.decode(b"call-7").as_deref(), Some("call-7"));
+        assert_eq!(IdEncoding::U64Be.decode(&[0, 0, 0, 0, 0, 0, 0, 9]).as_deref(), Some("9"));
+        
+        // Integer encodings only take exactly their width
+        assert_eq!(IdEncoding::U32Be.decode(&bytes[..3]), None);
+        assert_eq!(IdEncoding::U64Be.decode(&bytes), None);
+        assert_eq!(IdEncoding::Hex.fixed_width(), None);
+    }
+    
+    #[test]
+    fn values_past_the_payload_are_counted_not_read() {
+        let metrics = test_metrics();
+        let mut config = test_measurement();
+        config.metadata_extraction.id_patterns = vec![serde_yaml::from_str(
+            r#"{ pattern: '\xca\xfe', id_type: call_id, value_offset: 2, value_length: 4, encoding: u32_be }"#,
+        ).unwrap()];
+        let out_of_bounds = metrics.id_out_of_bounds.with_label_values(&[&config.name, "call_id"]);
+        let detector = SignatureDetector::new(config, 0, &metrics);
+        
+        let mut packet = vec![0xca, 0xfe, 0, 0, 0, 7];
+        packet.resize(320, 0);
+        assert_eq!(detector.extract_metadata(&packet).ids.get("call_id").map(String::as_str), Some("7"));
+        assert_eq!(out_of_bounds.get(), 0);
+        
+        // Marker in the last bytes: the value would run off the end
+        let mut truncated = vec![0; 316];
+        truncated.extend([0xca, 0xfe, 0, 0]);
+        assert!(detector.extract_metadata(&truncated).ids.is_empty());
+        assert_eq!(out_of_bounds.get(), 1);
+    }
+    
+    #[test]
+    fn ids_from_separate_packets_combine_and_the_first_value_wins() {
+        let metrics = test_metrics();
+        let mut config = tagged_measurement();
+        config.metadata_extraction.id_patterns.push(serde_yaml::from_str(
+            r#"{ pattern: 'call=(\w+);', id_type: call_id, value_offset: 0, value_length: 0 }"#,
+        ).unwrap());
+        let conflicts = metrics.metadata_conflicts.with_label_values(&[&config.name, "call_id"]);
+        let mut detector = SignatureDetector::new(config, 0, &metrics);
+        
+        let call = |id: &str| {
+            let mut packet = format!("call={};", id).into_bytes();
+            packet.resize(320, 0);
+            packet
+        };
+        detector.detect(&call("a"), 0);
+        detector.detect(&tagged("1"), 0);
+        detector.detect(&call("b"), 0);
+        
+        let ids = &detector.metadata.ids;
+        assert_eq!((ids["call_id"].as_str(), ids["interval_id"].as_str()), ("a", "1"));
+        assert_eq!(conflicts.get(), 1);
+    }
+    
+    #[test]
+    fn composite_grouping_keys_need_every_field() {
+        let mut correlation = test_measurement().correlation;
+        correlation.grouping_key = "call_id, channel".to_string();
+        let mut metadata = PacketMetadata::default();
+        metadata.ids.insert("call_id".to_string(), "c7".to_string());
+        assert_eq!(metadata.grouping_key(&correlation), None);
+        
+        metadata.ids.insert("channel".to_string(), "2".to_string());
+        assert_eq!(metadata.grouping_key(&correlation).as_deref(), Some("c7/2"));
+    }
+    
+    #[test]
+    fn warmup_holds_back_the_first_packets_of_each_interval() {
+        let mut config = tagged_measurement();
+        config.signature_rules.warmup_packets = 12;
+        let mut detector = SignatureDetector::new(config, 0, &test_metrics());
+        let mut first_signature = |interval_id: &str| {
+            tone(0.5, 20).into_iter().position(|mut packet| {
+                packet[..6].copy_from_slice(format!("iid={};", interval_id).as_bytes());
+                detector.detect(&packet, 0).is_some()
+            })
+        };
+        
+        // Without warmup the 10th packet fills the 200ms window
+        assert_eq!(first_signature("1"), Some(12));
+        assert_eq!(first_signature("2"), Some(12));
+    }
+    
+    #[test]
+    fn odd_sized_payloads_are_counted_and_decoded_in_step() {
+        let metrics = test_metrics();
+        let config = test_measurement();
+        let misaligned = metrics.misaligned_payloads.with_label_values(&[&config.name]);
+        let audio = tone(0.5, 20).concat();
+        
+        let mut detector = SignatureDetector::new(config, 0, &metrics);
+        let event = audio.chunks(99).find_map(|chunk| detector.detect(chunk, 0)).unwrap();
+        
+        // Off-by-a-byte samples would decode as full-scale noise
+        assert!((event.signature.confidence - 0.354).abs() < 0.01, "{}", event.signature.confidence);
+        assert!(misaligned.get() > 0);
+    }
+    
+    #[test]
+    fn later_patterns_only_fill_ids_the_earlier_ones_missed() {
+        let mut config = tagged_measurement();
+        config.metadata_extraction.id_patterns.push(serde_yaml::from_str(
+            r#"{ pattern: 'seg=(\w+);', id_type: interval_id, value_offset: 0, value_length: 0 }"#,
+        ).unwrap());
+        let detector = SignatureDetector::new(config, 0, &test_metrics());
+        let interval = |payload: &[u8]| detector.extract_metadata(payload).ids.get("interval_id").cloned();
+        
+        assert_eq!(interval(b"seg=9;iid=1;").as_deref(), Some("1"));
+        assert_eq!(interval(b"seg=9;").as_deref(), Some("9"));
+        assert_eq!(interval(b"none"), None);
+    }
+    
+    #[test]
+    fn only_allowlisted_intervals_are_buffered() {
+        let metrics = test_metrics();
+        let mut config = tagged_measurement();
+        config.correlation.interval_id_allowlist = Some("^vip".to_string());
+        let chunks = metrics.audio_buffer_chunks.with_label_values(&[&config.name]);
+        let mut detector = SignatureDetector::new(config, 0, &metrics);
+        
+        // Nothing is known about the interval until its id shows up
+        detector.detect(&[0; 320], 0);
+        for interval_id in ["x1", "x1", "vip1", "vip1", "x2"] {
+            detector.detect(&tagged(interval_id), 0);
+        }
+        assert_eq!(chunks.get(), 2);
+    }
+    
+    /// Deterministic ciphertext stand-in (xorshift)
+    fn noise(packets: usize, mut state: u64) -> Vec<Vec<u8>> {
+        (0..packets)
+            .map(|_| {
+                (0..320)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        state as u8
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn ciphertext_looks_random_and_audio_does_not() {
+        assert!(noise(5, 1).iter().all(|packet| looks_random(packet)));
+        assert!(!tone(0.5, 5).iter().any(|packet| looks_random(packet)));
+        assert!(!looks_random(&noise(1, 1)[0][..MIN_ENTROPY_SAMPLE - 1]));
+    }
+    
+    #[test]
+    fn srtp_signs_the_first_packet_of_each_talkspurt() {
+        let mut config = test_measurement();
+        config.signature_rules.audio_criteria.encryption = PayloadEncryption::Srtp;
+        let mut detector = SignatureDetector::new(config, 3, &test_metrics());
+        let packets = noise(10, 7);
+        
+        // 20ms apart, with a silence-suppression gap before the sixth
+        let signed: Vec<usize> = packets.iter()
+            .enumerate()
+            .filter_map(|(i, packet)| {
+                let at = i as u64 * 20_000_000 + if i >= 5 { TALKSPURT_GAP_NS } else { 0 };
+                let event = detector.detect(packet, at)?;
+                assert_eq!(event.signature.hash, xxhash_rust::xxh3::xxh3_64_with_seed(packet, 3));
+                Some(i)
+            })
+            .collect();
+        assert_eq!(signed, vec![0, 5]);
+    }
+    
+    #[test]
+    fn every_analysed_packet_is_timed() {
+        for (vad_mode, timed) in [(VadMode::Energy, 3), (VadMode::Tone { frequency_hz: 1000.0, threshold: 0.5 }, 9)] {
+            let metrics = test_metrics();
+            let mut config = test_measurement();
+            config.signature_rules.sampling_rate = 3;
+            config.signature_rules.audio_criteria.vad_mode = vad_mode;
+            let histogram = metrics.packet_processing_seconds.with_label_values(&[&config.name]);
+            
+            // Tone onsets need every packet, so tone mode isn't sampled
+            let mut detector = SignatureDetector::new(config, 0, &metrics);
+            for packet in tone(0.5, 9) {
+                detector.detect(&packet, 0);
+            }
+            assert_eq!(histogram.get_sample_count(), timed);
+        }
+    }
+    
+    #[test]
+    fn search_region_hides_decoys_outside_it() {
+        let mut config = test_measurement();
+        config.metadata_extraction.id_patterns = vec![
+            serde_yaml::from_str(
+                r#"{ pattern: 'iid=(\w+);', id_type: interval_id, value_offset: 0, value_length: 0, search_start: 12, search_end: 24 }"#,
+            ).unwrap(),
+            // The marker must be in the region; its value may lie past the end
+            serde_yaml::from_str(
+                r#"{ pattern: '\x7e', id_type: call_id, value_offset: 1, value_length: 2, encoding: hex, search_start: 24, search_end: 26 }"#,
+            ).unwrap(),
+        ];
+        let detector = SignatureDetector::new(config, 0, &test_metrics());
+        
+        let mut payload = b"iid=decoy;\x7e\x01\x02iid=real;".to_vec();
+        payload.extend_from_slice(&[0, 0, 0x7e, 0xab, 0xcd]);
+        let ids = detector.extract_metadata(&payload).ids;
+        assert_eq!(ids["interval_id"], "real");
+        assert_eq!(ids["call_id"], "abcd");
+    }
+    
+    #[test]
+    fn vad_waits_for_min_buffered_audio() {
+        let first = |min_buffered_ms| {
+            let mut config = test_measurement();
+            config.signature_rules.audio_criteria.min_buffered_ms = min_buffered_ms;
+            let mut detector = SignatureDetector::new(config, 0, &test_metrics());
+            tone(0.5, 30).iter().position(|packet| detector.detect(packet, 0).is_some())
+        };
+        
+        // Unset falls back to min_duration_ms (200ms: ten packets)
+        assert_eq!(first(None), Some(9));
+        assert_eq!(first(Some(400)), Some(19));
+    }
+    
+    #[test]
+    fn signatures_without_grouping_ids_follow_the_policy() {
+        let first_result = |policy| {
+            let metrics = test_metrics();
+            let mut config = test_measurement();
+            config.metadata_extraction.missing_metadata_policy = policy;
+            let missing = metrics.signatures_missing_metadata.with_label_values(&[&config.name]);
+            
+            // test_measurement groups by interval_id, which plain audio never carries
+            let mut detector = SignatureDetector::new(config, 0, &metrics);
+            let result = tone(0.5, 10).iter()
+                .map(|packet| detector.process_packet(packet, 0))
+                .find(|result| !matches!(result, Ok(None)));
+            (result, missing.get())
+        };
+        
+        let (emitted, counted) = first_result(MissingMetadataPolicy::EmitAnyway);
+        assert!(matches!(emitted, Some(Ok(Some(_)))) && counted == 1);
+        let (skipped, counted) = first_result(MissingMetadataPolicy::Skip);
+        assert!(skipped.is_none() && counted == 1);
+        
+        let (failed, _) = first_result(MissingMetadataPolicy::Error);
+        assert_eq!(
+            failed.unwrap().unwrap_err().to_string(),
+            "measurement 'test': signature missing required metadata [\"interval_id\"]"
+        );
+    }
+    
+    #[test]
+    fn each_buffer_full_policy_bounds_the_buffer_its_own_way() {
+        let run = |policy, min_duration_ms| {
+            let metrics = test_metrics();
+            let mut config = test_measurement();
+            config.signature_rules.buffer_full = policy;
+            config.signature_rules.audio_criteria.min_duration_ms = min_duration_ms;
+            let chunks = metrics.audio_buffer_chunks.with_label_values(&[&config.name]);
+            
+            let mut detector = SignatureDetector::new(config, 0, &metrics);
+            let signed: Vec<usize> = tone(0.5, 120).iter()
+                .enumerate()
+                .filter_map(|(i, packet)| detector.detect(packet, 0).map(|_| i))
+                .collect();
+            (signed, chunks.get())
+        };
+        
+        let (signed, buffered) = run(BufferFullPolicy::FifoDrop, 200);
+        assert_eq!((signed.len(), buffered), (111, BUFFER_CAPACITY as i64));
+        // Whole windows only, each starting empty
+        assert_eq!(run(BufferFullPolicy::EmitOnFull, 200), (vec![49, 99], 20));
+        // 2s of 20ms packets outgrows the 50-packet capacity
+        assert_eq!(run(BufferFullPolicy::GrowToDuration, 2000).1, 100);
+    }
+}
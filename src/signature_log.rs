@@ -0,0 +1,196 @@
+use crate::config::SignatureLogConfig;
+use crate::signature_detector::SignatureEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// One detected signature as written to a pod's signature log (JSON lines)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureLogRecord {
+    /// Sensor that detected it (the DaemonSet pod)
+    pub sensor: String,
+    /// Pod whose traffic carried the audio, when known
+    pub pod: Option<String>,
+    pub measurement: String,
+    pub hash: u64,
+    pub seed: u64,
+    pub duration_ms: u32,
+    /// Wall-clock nanos since the epoch
+    pub captured_at_ns: u64,
+    pub generated_at_ns: u64,
+    pub ids: HashMap<String, String>,
+}
+
+impl SignatureLogRecord {
+    /// Where the audio was observed; sensors only know their own name for
+    /// traffic without pod attribution
+    fn location(&self) -> &str {
+        self.pod.as_deref().unwrap_or(&self.sensor)
+    }
+}
+
+/// Appends every signature this pod detects to a local file, for matching
+/// offline across pods with `--match-logs`
+pub struct SignatureLog {
+    sensor: String,
+    file: Mutex<File>,
+}
+
+impl SignatureLog {
+    pub fn open(config: &SignatureLogConfig) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+        // Kubernetes sets HOSTNAME to the pod name
+        let sensor = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string());
+        Ok(Self { sensor, file: Mutex::new(file) })
+    }
+    
+    pub fn append(&self, event: &SignatureEvent) -> std::io::Result<()> {
+        let record = SignatureLogRecord {
+            sensor: self.sensor.clone(),
+            pod: event.source_pod.clone(),
+            measurement: event.measurement_name.clone(),
+            hash: event.signature.hash,
+            seed: event.signature.seed,
+            duration_ms: event.signature.duration_ms,
+            captured_at_ns: event.captured_at_ns,
+            generated_at_ns: event.generated_at_ns,
+            ids: event.metadata.ids.clone(),
+        };
+        
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        // One write per record keeps lines whole across detector threads
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+/// The same audio observed at two locations
+#[derive(Debug)]
+pub struct OfflineMatch {
+    pub measurement: String,
+    pub hash: u64,
+    pub source_pod: String,
+    pub relay_pod: String,
+    pub interval_id: Option<String>,
+    pub latency: Duration,
+}
+
+/// Read signature logs collected from several pods and pair up identical
+/// signatures: the earliest sighting is the source, and the first sighting
+/// at each other location is a hop with its own latency
+pub fn match_logs(paths: &[String]) -> Result<Vec<OfflineMatch>, String> {
+    let mut sightings: HashMap<(String, u64, u64), Vec<SignatureLogRecord>> = HashMap::new();
+    
+    for path in paths {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: SignatureLogRecord = serde_json::from_str(&line)
+                .map_err(|e| format!("{}:{}: {}", path, line_no + 1, e))?;
+            sightings.entry((record.measurement.clone(), record.seed, record.hash))
+                .or_default()
+                .push(record);
+        }
+    }
+    
+    let mut matches = Vec::new();
+    for ((measurement, _, hash), mut records) in sightings {
+        records.sort_by_key(|r| r.captured_at_ns);
+        let source = &records[0];
+        let mut seen = vec![source.location()];
+        
+        for record in &records[1..] {
+            if seen.contains(&record.location()) {
+                continue;
+            }
+            seen.push(record.location());
+            matches.push(OfflineMatch {
+                measurement: measurement.clone(),
+                hash,
+                source_pod: source.location().to_string(),
+                relay_pod: record.location().to_string(),
+                interval_id: source.ids.get("interval_id").cloned(),
+                latency: Duration::from_nanos(record.captured_at_ns - source.captured_at_ns),
+            });
+        }
+    }
+    
+    matches.sort_by(|a, b| (&a.measurement, a.latency).cmp(&(&b.measurement, b.latency)));
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature_detector::{AudioSignature, PacketMetadata};
+    
+    fn event(hash: u64, pod: &str, captured_at_ns: u64) -> SignatureEvent {
+        let mut metadata = PacketMetadata::default();
+        metadata.ids.insert("interval_id".to_string(), "42".to_string());
+        SignatureEvent {
+            signature: AudioSignature {
+                hash,
+                duration_ms: 200,
+                seed: 0,
+                onset_sample: None,
+                confidence: 1.0,
+                fingerprint: Vec::new(),
+            },
+            metadata,
+            captured_at_ns,
+            generated_at_ns: captured_at_ns,
+            measurement_name: "calls".to_string(),
+            source_pod: Some(pod.to_string()),
+        }
+    }
+    
+    /// A fresh log file holding `events`
+    fn log(name: &str, events: &[SignatureEvent]) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.display().to_string();
+        let log = SignatureLog::open(&SignatureLogConfig { path: path.clone() }).unwrap();
+        for event in events {
+            log.append(event).unwrap();
+        }
+        path
+    }
+    
+    #[test]
+    fn logs_from_each_pod_pair_into_hops() {
+        let source = log("source-log", &[event(1, "source-0", 1_000_000), event(2, "source-0", 5_000_000)]);
+        // The relay saw hash 1 twice (repeat) and hash 2 never
+        let relay = log("relay-log", &[event(1, "relay-0", 4_000_000), event(1, "relay-0", 9_000_000)]);
+        let edge = log("edge-log", &[event(1, "edge-0", 11_000_000)]);
+        
+        let matches = match_logs(&[source.clone(), relay.clone(), edge.clone()]).unwrap();
+        let hops: Vec<(&str, &str, Duration)> = matches.iter()
+            .map(|m| (m.source_pod.as_str(), m.relay_pod.as_str(), m.latency))
+            .collect();
+        assert_eq!(hops, vec![
+            ("source-0", "relay-0", Duration::from_millis(3)),
+            ("source-0", "edge-0", Duration::from_millis(10)),
+        ]);
+        assert_eq!(matches[0].interval_id.as_deref(), Some("42"));
+        
+        for path in [source, relay, edge] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+    
+    #[test]
+    fn unreadable_lines_name_their_file_and_line() {
+        let path = log("bad-log", &[event(1, "source-0", 0)]);
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{not json\n").unwrap();
+        
+        let error = match_logs(std::slice::from_ref(&path)).unwrap_err();
+        assert!(error.starts_with(&format!("{}:2: ", path)), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use crate::signature_detector::SignatureEvent;
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Concurrency-safe store of signatures awaiting a match, shared by every
+/// measurement. Entries are namespaced by measurement name, but lookups can
+/// cross namespaces so audio that migrates between pipelines still correlates.
+/// Several pending signatures can share a hash (every tone onset of a
+/// measurement does), so each namespace queues them oldest capture first and
+/// a match claims the oldest.
+#[derive(Default)]
+pub struct SignatureStore {
+    // hash -> (measurement -> [(event, inserted at)] by captured_at_ns)
+    entries: DashMap<u64, HashMap<String, VecDeque<(SignatureEvent, Instant)>>>,
+}
+
+impl SignatureStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Track a signature under its own measurement's namespace
+    pub fn insert(&self, event: SignatureEvent) {
+        let mut namespaces = self.entries.entry(event.signature.hash).or_default();
+        let queue = namespaces.entry(event.measurement_name.clone()).or_default();
+        // Broadcasts from several pods can arrive out of capture order
+        let at = queue.partition_point(|(queued, _)| queued.captured_at_ns <= event.captured_at_ns);
+        queue.insert(at, (event, Instant::now()));
+    }
+    
+    /// Remove and return the oldest signature for `hash` within one measurement
+    pub fn take(&self, measurement: &str, hash: u64) -> Option<SignatureEvent> {
+        let mut namespaces = self.entries.get_mut(&hash)?;
+        let queue = namespaces.get_mut(measurement)?;
+        let event = queue.pop_front().map(|(event, _)| event);
+        if queue.is_empty() {
+            namespaces.remove(measurement);
+        }
+        let now_empty = namespaces.is_empty();
+        drop(namespaces);
+        
+        if now_empty {
+            self.entries.remove_if(&hash, |_, namespaces| namespaces.is_empty());
+        }
+        event
+    }
+    
+    /// Remove and return a signature for `hash` from any measurement,
+    /// preferring `preferred` when the same audio is tracked by several,
+    /// then whichever VAD fired most confidently
+    pub fn take_any(&self, hash: u64, preferred: Option<&str>) -> Option<SignatureEvent> {
+        if let Some(measurement) = preferred {
+            if let Some(event) = self.take(measurement, hash) {
+                return Some(event);
+            }
+        }
+        
+        let measurement = self.entries.get(&hash)?
+            .iter()
+            .filter_map(|(measurement, queue)| Some((measurement, &queue.front()?.0)))
+            .max_by(|(_, a), (_, b)| a.signature.confidence.total_cmp(&b.signature.confidence))
+            .map(|(measurement, _)| measurement.clone())?;
+        self.take(&measurement, hash)
+    }
+    
+    /// Remove signatures older than their measurement's TTL and return them;
+    /// anything returned here was never matched
+    pub fn evict_expired(&self, ttl_for: impl Fn(&str) -> Duration) -> Vec<SignatureEvent> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        
+        self.entries.retain(|_, namespaces| {
+            namespaces.retain(|measurement, queue| {
+                let ttl = ttl_for(measurement);
+                let (stale, fresh): (VecDeque<_>, VecDeque<_>) = std::mem::take(queue)
+                    .into_iter()
+                    .partition(|(_, inserted_at)| now.duration_since(*inserted_at) > ttl);
+                expired.extend(stale.into_iter().map(|(event, _)| event));
+                *queue = fresh;
+                !queue.is_empty()
+            });
+            !namespaces.is_empty()
+        });
+        
+        expired
+    }
+    
+    /// Signatures currently tracked across all namespaces
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.iter()
+            .map(|entry| entry.value().values().map(VecDeque::len).sum::<usize>())
+            .sum()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature_detector::{AudioSignature, PacketMetadata};
+    
+    fn event(measurement: &str, hash: u64, captured_at_ns: u64) -> SignatureEvent {
+        SignatureEvent {
+            signature: AudioSignature {
+                hash,
+                duration_ms: 0,
+                seed: 0,
+                onset_sample: None,
+                confidence: 0.5,
+                fingerprint: Vec::new(),
+            },
+            metadata: PacketMetadata::default(),
+            captured_at_ns,
+            generated_at_ns: captured_at_ns,
+            measurement_name: measurement.to_string(),
+            source_pod: None,
+        }
+    }
+    
+    #[test]
+    fn shared_hashes_queue_oldest_capture_first() {
+        let store = SignatureStore::new();
+        store.insert(event("tone", 1, 300));
+        store.insert(event("tone", 1, 100));
+        store.insert(event("tone", 1, 200));
+        assert_eq!(store.len(), 3);
+        
+        let order: Vec<u64> = std::iter::from_fn(|| store.take("tone", 1)).map(|e| e.captured_at_ns).collect();
+        assert_eq!(order, vec![100, 200, 300]);
+        assert!(store.is_empty());
+    }
+    
+    #[test]
+    fn take_any_prefers_the_named_measurement_then_confidence() {
+        let store = SignatureStore::new();
+        let mut confident = event("calls", 7, 100);
+        confident.signature.confidence = 0.9;
+        store.insert(confident);
+        store.insert(event("prompts", 7, 100));
+        store.insert(event("greetings", 7, 100));
+        
+        assert_eq!(store.take_any(7, Some("prompts")).unwrap().measurement_name, "prompts");
+        assert_eq!(store.take_any(7, Some("absent")).unwrap().measurement_name, "calls");
+        assert_eq!(store.take_any(7, None).unwrap().measurement_name, "greetings");
+        assert!(store.take_any(7, None).is_none());
+    }
+    
+    #[test]
+    fn namespaces_expire_on_their_own_ttl() {
+        let store = SignatureStore::new();
+        store.insert(event("short", 1, 100));
+        store.insert(event("long", 1, 100));
+        store.insert(event("short", 2, 100));
+        std::thread::sleep(Duration::from_millis(20));
+        
+        let ttl = |m: &str| if m == "short" { Duration::ZERO } else { Duration::from_secs(60) };
+        let expired = store.evict_expired(ttl);
+        assert_eq!(expired.len(), 2);
+        assert!(expired.iter().all(|e| e.measurement_name == "short"));
+        assert_eq!(store.len(), 1);
+        assert!(store.take("long", 1).is_some());
+    }
+    
+    #[test]
+    fn only_unmatched_signatures_expire() {
+        let store = SignatureStore::new();
+        store.insert(event("tone", 1, 100));
+        store.insert(event("tone", 2, 100));
+        assert!(store.take("tone", 1).is_some());
+        
+        let expired = store.evict_expired(|_| Duration::ZERO);
+        assert_eq!(expired.iter().map(|e| e.signature.hash).collect::<Vec<_>>(), vec![2]);
+        assert!(store.is_empty());
+        assert!(store.evict_expired(|_| Duration::ZERO).is_empty());
+    }
+}
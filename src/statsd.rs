@@ -0,0 +1,151 @@
+use crate::config::StatsdConfig;
+use std::net::UdpSocket;
+use std::sync::Mutex;
+
+/// Buffers StatsD lines and ships them over UDP in MTU-sized datagrams
+pub struct StatsdSink {
+    config: StatsdConfig,
+    socket: UdpSocket,
+    buffer: Mutex<String>,
+}
+
+impl StatsdSink {
+    pub fn new(config: StatsdConfig) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(&config.host)?;
+        socket.set_nonblocking(true)?;
+        
+        Ok(Self {
+            buffer: Mutex::new(String::with_capacity(config.max_datagram_bytes)),
+            config,
+            socket,
+        })
+    }
+    
+    /// Record a timing in milliseconds, e.g. `audio.latency:12.5|ms`
+    pub fn timing(&self, name: &str, ms: f64, tags: &[(&str, &str)]) {
+        self.push(self.format_line(name, &format!("{}", ms), "ms", tags));
+    }
+    
+    /// Increment a counter, e.g. `audio.signatures.detected:1|c`
+    pub fn count(&self, name: &str, value: i64, tags: &[(&str, &str)]) {
+        self.push(self.format_line(name, &value.to_string(), "c", tags));
+    }
+    
+    /// Send whatever is buffered, even if the datagram isn't full
+    pub fn flush(&self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        self.send(&mut buffer);
+    }
+    
+    fn format_line(&self, name: &str, value: &str, kind: &str, tags: &[(&str, &str)]) -> String {
+        let mut line = format!("{}.{}:{}|{}", self.config.prefix, name, value, kind);
+        
+        if self.config.tags && !tags.is_empty() {
+            let tags: Vec<String> = tags.iter()
+                .map(|(k, v)| format!("{}:{}", k, sanitize_tag(v)))
+                .collect();
+            line.push_str("|#");
+            line.push_str(&tags.join(","));
+        }
+        
+        line
+    }
+    
+    fn push(&self, line: String) {
+        let mut buffer = self.buffer.lock().unwrap();
+        
+        // Lines are newline-separated; flush first if this one would overflow
+        if !buffer.is_empty() && buffer.len() + 1 + line.len() > self.config.max_datagram_bytes {
+            self.send(&mut buffer);
+        }
+        
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        
+        // A single oversized line still goes out on its own
+        if buffer.len() >= self.config.max_datagram_bytes {
+            self.send(&mut buffer);
+        }
+    }
+    
+    fn send(&self, buffer: &mut String) {
+        if buffer.is_empty() {
+            return;
+        }
+        
+        // Metrics are best-effort; a full socket buffer or absent agent is not fatal
+        if let Err(e) = self.socket.send(buffer.as_bytes()) {
+            tracing::debug!("statsd send to {} failed: {}", self.config.host, e);
+        }
+        buffer.clear();
+    }
+}
+
+/// `:`, `,` and `|` are delimiters in the DogStatsD tag syntax
+fn sanitize_tag(value: &str) -> String {
+    value.replace([':', ',', '|'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    
+    /// A sink pointed at a local socket standing in for the agent
+    fn sink(tags: bool, max_datagram_bytes: usize) -> (StatsdSink, UdpSocket) {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let config = StatsdConfig {
+            host: agent.local_addr().unwrap().to_string(),
+            prefix: "audio".to_string(),
+            tags,
+            max_datagram_bytes,
+            flush_interval_ms: 1000,
+        };
+        (StatsdSink::new(config).unwrap(), agent)
+    }
+    
+    fn receive(agent: &UdpSocket) -> String {
+        let mut datagram = [0u8; 1500];
+        let len = agent.recv(&mut datagram).unwrap();
+        String::from_utf8_lossy(&datagram[..len]).into_owned()
+    }
+    
+    #[test]
+    fn lines_carry_prefix_kind_and_sanitized_tags() {
+        let (sink, agent) = sink(true, 1432);
+        sink.timing("latency", 12.5, &[("measurement", "calls"), ("pod", "a:b,c|d")]);
+        sink.count("signatures.matched", 1, &[]);
+        sink.flush();
+        
+        assert_eq!(
+            receive(&agent),
+            "audio.latency:12.5|ms|#measurement:calls,pod:a_b_c_d\naudio.signatures.matched:1|c",
+        );
+    }
+    
+    #[test]
+    fn tags_are_left_off_unless_enabled() {
+        let (sink, agent) = sink(false, 1432);
+        sink.count("signatures.detected", 3, &[("measurement", "calls")]);
+        sink.flush();
+        
+        assert_eq!(receive(&agent), "audio.signatures.detected:3|c");
+    }
+    
+    #[test]
+    fn datagrams_never_exceed_the_limit() {
+        // Each line is 25 bytes: two and a newline fill 51 exactly, a third must wait
+        let (sink, agent) = sink(false, 51);
+        for _ in 0..3 {
+            sink.count("signatures.lost", 1, &[]);
+        }
+        sink.flush();
+        
+        assert_eq!(receive(&agent), "audio.signatures.lost:1|c\naudio.signatures.lost:1|c");
+        assert_eq!(receive(&agent), "audio.signatures.lost:1|c");
+    }
+}
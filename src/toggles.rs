@@ -0,0 +1,72 @@
+use crate::config::MeasurementConfig;
+use crate::metrics::SensorMetrics;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Runtime on/off switches for this pod's running measurements, flipped over
+/// HTTP without touching config. Pod-local: other sensors keep their own state.
+#[derive(Clone)]
+pub struct MeasurementToggles {
+    flags: Arc<HashMap<String, AtomicBool>>,
+    enabled: prometheus::IntGaugeVec,
+}
+
+impl MeasurementToggles {
+    /// Every enabled measurement starts switched on
+    pub fn new(measurements: &[MeasurementConfig], metrics: &SensorMetrics) -> Self {
+        let enabled = metrics.measurement_enabled.clone();
+        let flags = measurements.iter()
+            .filter(|m| m.enabled)
+            .map(|m| {
+                enabled.with_label_values(&[&m.name]).set(1);
+                (m.name.clone(), AtomicBool::new(true))
+            })
+            .collect();
+        
+        Self { flags: Arc::new(flags), enabled }
+    }
+    
+    /// Measurements this pod doesn't run can't be switched off here
+    pub fn is_enabled(&self, measurement: &str) -> bool {
+        self.flags.get(measurement).is_none_or(|flag| flag.load(Ordering::Relaxed))
+    }
+    
+    /// Returns false if `measurement` isn't running on this pod
+    pub fn set(&self, measurement: &str, enabled: bool) -> bool {
+        let Some(flag) = self.flags.get(measurement) else {
+            return false;
+        };
+        
+        flag.store(enabled, Ordering::Relaxed);
+        self.enabled.with_label_values(&[measurement]).set(enabled as i64);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    use crate::metrics::test_metrics;
+    
+    #[test]
+    fn only_running_measurements_can_be_switched() {
+        let metrics = test_metrics();
+        let mut disabled = test_measurement();
+        disabled.name = "disabled".to_string();
+        disabled.enabled = false;
+        let toggles = MeasurementToggles::new(&[test_measurement(), disabled], &metrics);
+        let gauge = |name: &str| metrics.measurement_enabled.with_label_values(&[name]).get();
+        assert_eq!(gauge("test"), 1);
+        
+        assert!(toggles.set("test", false));
+        assert!(!toggles.is_enabled("test"));
+        assert_eq!(gauge("test"), 0);
+        
+        // Not run here: never switched, never reported off
+        assert!(!toggles.set("disabled", false));
+        assert!(toggles.is_enabled("disabled"));
+        assert!(toggles.clone().set("test", true) && toggles.is_enabled("test"));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::{MeasurementConfig, VadMode};
+use crate::rtp;
+use crate::sample_decode::{decode_samples, downmix};
+use crate::signature_detector::SignatureEvent;
+use crate::signature_store::SignatureStore;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Goertzel power at `frequency_hz`, normalized by the block's total energy so a
+/// pure tone scores ~1.0 regardless of level and broadband audio scores near 0
+pub fn goertzel_ratio(samples: &[f32], frequency_hz: f32, sample_rate: u32) -> f32 {
+    let n = samples.len();
+    if n == 0 {
+        return 0.0;
+    }
+    
+    let omega = 2.0 * std::f32::consts::PI * frequency_hz / sample_rate as f32;
+    let coeff = 2.0 * omega.cos();
+    let (mut s_prev, mut s_prev2) = (0.0f32, 0.0f32);
+    let mut energy = 0.0f32;
+    
+    for &x in samples {
+        let s = x + coeff * s_prev - s_prev2;
+        s_prev2 = s_prev;
+        s_prev = s;
+        energy += x * x;
+    }
+    
+    if energy == 0.0 {
+        return 0.0;
+    }
+    
+    let power = s_prev * s_prev + s_prev2 * s_prev2 - coeff * s_prev * s_prev2;
+    2.0 * power / (n as f32 * energy)
+}
+
+/// Stream-level detector for the onset of an injected reference tone
+pub struct ToneOnsetDetector {
+    frequency_hz: f32,
+    sample_rate: u32,
+    threshold: f32,
+    block_len: usize,
+    pending: Vec<f32>,
+    samples_seen: u64,
+    tone_active: bool,
+}
+
+impl ToneOnsetDetector {
+    pub fn new(frequency_hz: f32, sample_rate: u32, threshold: f32) -> Self {
+        // 10ms blocks: enough resolution for timing, enough samples for the filter
+        let block_len = (sample_rate as usize / 100).max(32);
+        Self {
+            frequency_hz,
+            sample_rate,
+            threshold,
+            block_len,
+            pending: Vec::with_capacity(block_len),
+            samples_seen: 0,
+            tone_active: false,
+        }
+    }
+    
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    
+    /// Feed decoded samples; returns the stream sample position of a tone onset
+    /// (start of the first block where the tone dominates after it was absent)
+    /// and the tone's share of that block's energy
+    pub fn feed(&mut self, samples: &[f32]) -> Option<(u64, f32)> {
+        let mut onset = None;
+        
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() < self.block_len {
+                continue;
+            }
+            
+            let block_start = self.samples_seen;
+            self.samples_seen += self.block_len as u64;
+            let ratio = goertzel_ratio(&self.pending, self.frequency_hz, self.sample_rate);
+            let present = ratio > self.threshold;
+            self.pending.clear();
+            
+            if present && !self.tone_active && onset.is_none() {
+                onset = Some((block_start, ratio));
+            }
+            self.tone_active = present;
+        }
+        
+        onset
+    }
+}
+
+/// Tone onsets carry no audio content to fingerprint, so both the detector and
+/// the matcher derive the same hash from the measurement and tone frequency.
+/// Concurrent streams' onsets therefore share it; the store keeps them all
+/// and pairs each relayed onset with the oldest pending one.
+pub fn tone_signature_hash(measurement: &str, frequency_hz: f32, seed: u64) -> u64 {
+    let key = format!("tone:{}:{}", measurement, frequency_hz);
+    xxhash_rust::xxh3::xxh3_64_with_seed(key.as_bytes(), seed)
+}
+
+/// Matcher side of tone mode: watches relayed streams for each tone
+/// measurement's onset and claims the stored signature it corresponds to
+pub struct ToneMatcher {
+    // (measurement, tone frequency, threshold)
+    measurements: Vec<(MeasurementConfig, f32, f32)>,
+    // (measurement, stream) -> (detector, last payload seen)
+    detectors: HashMap<(String, String), (ToneOnsetDetector, Instant)>,
+}
+
+impl ToneMatcher {
+    pub fn new(measurements: &[MeasurementConfig]) -> Self {
+        let measurements = measurements.iter()
+            .filter(|m| m.enabled)
+            .filter_map(|m| match m.signature_rules.audio_criteria.vad_mode {
+                VadMode::Tone { frequency_hz, threshold } => Some((m.clone(), frequency_hz, threshold)),
+                _ => None,
+            })
+            .collect();
+        
+        Self { measurements, detectors: HashMap::new() }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.measurements.is_empty()
+    }
+    
+    /// Feed one relayed payload from `stream`; returns signatures whose tone
+    /// onset it completed
+    pub fn observe(&mut self, stream: &str, payload: &[u8], store: &SignatureStore, hash_seed: u64) -> Vec<SignatureEvent> {
+        let mut matched = Vec::new();
+        
+        for (measurement, frequency_hz, threshold) in &self.measurements {
+            let Some((audio, format)) = rtp::audio_payload(payload, measurement) else {
+                continue;
+            };
+            
+            let (detector, last_seen) = self.detectors
+                .entry((measurement.name.clone(), stream.to_string()))
+                .or_insert_with(|| (ToneOnsetDetector::new(*frequency_hz, format.sample_rate, *threshold), Instant::now()));
+            *last_seen = Instant::now();
+            if detector.sample_rate() != format.sample_rate {
+                // Payload type changed mid-stream; restart at the new rate
+                *detector = ToneOnsetDetector::new(*frequency_hz, format.sample_rate, *threshold);
+            }
+            
+            let samples = downmix(decode_samples(audio, &format.sample_format), format.channels);
+            if detector.feed(&samples).is_some() {
+                let hash = tone_signature_hash(&measurement.name, *frequency_hz, hash_seed);
+                matched.extend(store.take(&measurement.name, hash));
+            }
+        }
+        
+        matched
+    }
+    
+    /// Drop streams silent for longer than their measurement's signature TTL:
+    /// no onset they could still match is left in the store
+    pub fn prune_idle(&mut self) {
+        let ttls: HashMap<&str, Duration> = self.measurements.iter()
+            .map(|(m, _, _)| (m.name.as_str(), Duration::from_secs(m.correlation.signature_ttl_seconds)))
+            .collect();
+        self.detectors.retain(|(measurement, _), (_, last_seen)| {
+            ttls.get(measurement.as_str()).is_some_and(|ttl| last_seen.elapsed() < *ttl)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{test_measurement, SampleFormat};
+    use crate::metrics::test_metrics;
+    use crate::sample_decode::encode_samples;
+    use crate::signature_detector::SignatureDetector;
+    
+    fn tone(frequency_hz: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * frequency_hz * std::f32::consts::TAU / 8000.0).sin() * 0.5)
+            .collect()
+    }
+    
+    /// `silence_ms` of silence, then 200ms of 1kHz, as 20ms S16Le packets
+    fn onset_after(silence_ms: usize) -> Vec<Vec<u8>> {
+        let mut samples = vec![0.0; silence_ms * 8];
+        samples.extend(tone(1000.0, 1600));
+        encode_samples(&samples, &SampleFormat::S16Le)
+            .chunks(320)
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+    
+    fn tone_measurement() -> MeasurementConfig {
+        let mut measurement = test_measurement();
+        measurement.signature_rules.audio_criteria.vad_mode = VadMode::Tone { frequency_hz: 1000.0, threshold: 0.5 };
+        measurement
+    }
+    
+    #[test]
+    fn goertzel_singles_out_its_frequency() {
+        assert!(goertzel_ratio(&tone(1000.0, 800), 1000.0, 8000) > 0.9);
+        assert!(goertzel_ratio(&tone(2500.0, 800), 1000.0, 8000) < 0.05);
+        assert_eq!(goertzel_ratio(&[0.0; 80], 1000.0, 8000), 0.0);
+    }
+    
+    #[test]
+    fn onset_is_reported_once_at_its_block() {
+        let mut detector = ToneOnsetDetector::new(1000.0, 8000, 0.5);
+        assert!(detector.feed(&[0.0; 400]).is_none());
+        
+        let (onset, strength) = detector.feed(&tone(1000.0, 800)).unwrap();
+        assert_eq!(onset, 400);
+        assert!(strength > 0.5);
+        assert!(detector.feed(&tone(1000.0, 800)).is_none());
+    }
+    
+    #[test]
+    fn concurrent_onsets_pair_oldest_first() {
+        let measurement = tone_measurement();
+        let metrics = test_metrics();
+        let store = SignatureStore::new();
+        
+        // Two source streams whose onsets share the measurement's tone hash
+        for (stream, captured_at_ns) in [("b", 200), ("a", 100)] {
+            let mut detector = SignatureDetector::new(measurement.clone(), 0, &metrics);
+            let mut event = onset_after(100).iter()
+                .find_map(|packet| detector.detect(packet, captured_at_ns))
+                .unwrap();
+            event.source_pod = Some(stream.to_string());
+            store.insert(event);
+        }
+        assert_eq!(store.len(), 2);
+        
+        let mut matcher = ToneMatcher::new(std::slice::from_ref(&measurement));
+        let first = onset_after(40).iter()
+            .find_map(|packet| matcher.observe("relay-1", packet, &store, 0).pop())
+            .unwrap();
+        assert_eq!(first.source_pod.as_deref(), Some("a"));
+        
+        let second = onset_after(40).iter()
+            .find_map(|packet| matcher.observe("relay-2", packet, &store, 0).pop())
+            .unwrap();
+        assert_eq!(second.source_pod.as_deref(), Some("b"));
+        assert!(store.is_empty());
+    }
+    
+    #[test]
+    fn idle_streams_are_pruned() {
+        let mut measurement = tone_measurement();
+        measurement.correlation.signature_ttl_seconds = 0;
+        let mut matcher = ToneMatcher::new(&[measurement]);
+        matcher.observe("relay", &onset_after(0)[0], &SignatureStore::new(), 0);
+        assert_eq!(matcher.detectors.len(), 1);
+        
+        matcher.prune_idle();
+        assert!(matcher.detectors.is_empty());
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Renders a duration in milliseconds with fixed decimals, so log lines and
+/// reports line up and parse the same way whether the value is 340µs or 2s.
+/// Precision defaults to 3 places (microseconds); `{:.1}` overrides it.
+pub struct Millis(pub Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(f, "{:.*}ms", precision, self.0.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn millis_default_to_microsecond_places() {
+        assert_eq!(Millis(Duration::from_micros(340)).to_string(), "0.340ms");
+        assert_eq!(Millis(Duration::from_secs(2)).to_string(), "2000.000ms");
+        assert_eq!(format!("{:.1}", Millis(Duration::from_micros(12_345))), "12.3ms");
+        assert_eq!(format!("{:.0}", Millis(Duration::from_nanos(1_600_000))), "2ms");
+    }
+}
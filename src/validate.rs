@@ -0,0 +1,237 @@
+use crate::config::{SensorConfig, VadMode};
+use crate::pxl;
+use crate::signature_detector::parse_byte_pattern;
+use std::collections::HashSet;
+
+/// Outcome of a pre-flight config check. Errors would fail or misbehave at
+/// runtime; warnings are legal but probably not what was meant.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl SensorConfig {
+    /// Checks serde can't express: value ranges, cross-field consistency, and
+    /// that every id pattern compiles. Never touches the network.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let mut names = HashSet::new();
+        
+        if !self.measurements.iter().any(|m| m.enabled) {
+            report.warnings.push("no measurements are enabled".to_string());
+        }
+        // tokio's broadcast channel panics on a zero capacity
+        if self.signature_channel_capacity == 0 {
+            report.errors.push("signature_channel_capacity must be at least 1".to_string());
+        }
+        if self.local_signature_queue == Some(0) {
+            report.errors.push("local_signature_queue must be at least 1 (omit it to broadcast instead)".to_string());
+        }
+        let query = &self.matcher_query;
+        if query.window_secs == 0 {
+            report.errors.push("matcher_query.window_secs must be at least 1".to_string());
+        }
+        match query.refresh_secs {
+            Some(0) => report.errors.push("matcher_query.refresh_secs must be at least 1".to_string()),
+            Some(refresh) if refresh > query.window_secs => report.errors.push(format!(
+                "matcher_query.refresh_secs ({}) exceeds window_secs ({}); traffic between windows would be missed",
+                refresh, query.window_secs
+            )),
+            _ => {}
+        }
+        if let Err(e) = pxl::matcher_query(query) {
+            report.errors.push(format!("matcher_query: {}", e));
+        }
+        if self.statsd.as_ref().is_some_and(|statsd| statsd.flush_interval_ms == 0) {
+            report.errors.push("statsd.flush_interval_ms must be at least 1".to_string());
+        }
+        if self.jitter_buffer.as_ref().is_some_and(|jitter| jitter.frame_ms == 0) {
+            report.errors.push("jitter_buffer.frame_ms must be at least 1".to_string());
+        }
+        if self.metrics.hash_buckets == 0 {
+            report.errors.push("metrics.hash_buckets must be at least 1".to_string());
+        }
+        
+        for m in &self.measurements {
+            let mut error = |msg: String| report.errors.push(format!("measurement '{}': {}", m.name, msg));
+            let rules = &m.signature_rules;
+            let criteria = &rules.audio_criteria;
+            
+            if !names.insert(m.name.as_str()) {
+                error("duplicate measurement name".to_string());
+            }
+            if rules.sampling_rate == 0 {
+                error("sampling_rate must be at least 1".to_string());
+            }
+            if rules.detector_workers == 0 {
+                error("detector_workers must be at least 1".to_string());
+            }
+            if rules.shard_queue_depth == 0 {
+                error("shard_queue_depth must be at least 1".to_string());
+            }
+            if rules.stream_idle_secs == 0 {
+                error("stream_idle_secs must be at least 1".to_string());
+            }
+            if rules.max_signatures_per_sec.is_some_and(|rate| rate <= 0.0) {
+                error("max_signatures_per_sec must be positive".to_string());
+            }
+            if criteria.sample_rate == 0 {
+                error("sample_rate must be positive".to_string());
+            }
+            if let Some((low, high)) = criteria.frequency_range {
+                if low >= high {
+                    error(format!("frequency_range [{}, {}] is empty", low, high));
+                }
+            }
+            if let Some(allowlist) = &m.correlation.interval_id_allowlist {
+                if let Err(e) = regex::Regex::new(allowlist) {
+                    error(format!("interval_id_allowlist {:?}: {}", allowlist, e));
+                }
+            }
+            if let VadMode::Tone { frequency_hz, .. } = criteria.vad_mode {
+                let nyquist = criteria.sample_rate as f32 / 2.0;
+                if frequency_hz <= 0.0 || frequency_hz >= nyquist {
+                    error(format!("tone frequency {}Hz must be between 0 and {}Hz", frequency_hz, nyquist));
+                }
+            }
+            
+            for pattern in &m.metadata_extraction.id_patterns {
+                let compiled = if pattern.pattern.starts_with("\\x") {
+                    parse_byte_pattern(&pattern.pattern).map(|_| ())
+                } else {
+                    regex::Regex::new(&pattern.pattern).map(|_| ()).map_err(|e| e.to_string())
+                };
+                if let Err(e) = compiled {
+                    error(format!("{} pattern {:?}: {}", pattern.id_type, pattern.pattern, e));
+                }
+                if let (Some(start), Some(end)) = (pattern.search_start, pattern.search_end) {
+                    if start >= end {
+                        error(format!("{} pattern: search region [{}, {}) is empty", pattern.id_type, start, end));
+                    }
+                }
+                if let Some(width) = pattern.encoding.fixed_width() {
+                    if pattern.value_length != width {
+                        error(format!("{} pattern: {:?} encoding needs value_length {}, got {}",
+                            pattern.id_type, pattern.encoding, width, pattern.value_length));
+                    }
+                }
+            }
+            
+            if !(0.0..=1.0).contains(&criteria.energy_threshold) {
+                report.warnings.push(format!(
+                    "measurement '{}': energy_threshold {} is outside 0.0-1.0 and will never/always fire",
+                    m.name, criteria.energy_threshold
+                ));
+            }
+            if rules.stream_grace_secs.is_some_and(|grace| grace < rules.idle_warning_secs) {
+                report.warnings.push(format!(
+                    "measurement '{}': stream_grace_secs is below idle_warning_secs, so idle streams reconnect before they are reported",
+                    m.name
+                ));
+            }
+            if !m.enabled {
+                report.warnings.push(format!("measurement '{}' is disabled", m.name));
+            }
+        }
+        
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    
+    fn config() -> SensorConfig {
+        let mut config: SensorConfig = serde_yaml::from_str("measurements: []").unwrap();
+        config.measurements.push(test_measurement());
+        config
+    }
+    
+    #[test]
+    fn minimal_config_passes() {
+        let report = config().validate();
+        assert!(report.is_ok(), "{:?}", report.errors);
+    }
+    
+    #[test]
+    fn zero_capacities_and_intervals_are_errors() {
+        let mut config = config();
+        config.signature_channel_capacity = 0;
+        config.local_signature_queue = Some(0);
+        config.statsd = Some(serde_yaml::from_str("{ host: '127.0.0.1:8125', flush_interval_ms: 0 }").unwrap());
+        config.jitter_buffer = Some(serde_yaml::from_str("{ frame_ms: 0, initial_depth_ms: 40, max_depth_ms: 200 }").unwrap());
+        config.measurements[0].signature_rules.shard_queue_depth = 0;
+        
+        let errors = config.validate().errors;
+        for field in [
+            "signature_channel_capacity",
+            "local_signature_queue",
+            "statsd.flush_interval_ms",
+            "jitter_buffer.frame_ms",
+            "shard_queue_depth",
+        ] {
+            assert!(errors.iter().any(|e| e.contains(field)), "no error for {}: {:?}", field, errors);
+        }
+    }
+    
+    #[test]
+    fn measurement_errors_name_the_measurement_and_warnings_stay_separate() {
+        let mut config = config();
+        let mut twin = test_measurement();
+        twin.enabled = false;
+        twin.signature_rules.audio_criteria.energy_threshold = 2.0;
+        twin.metadata_extraction.id_patterns = vec![serde_yaml::from_str(
+            r#"{ pattern: 'iid=(', id_type: interval_id, value_offset: 0, value_length: 0 }"#,
+        ).unwrap()];
+        config.measurements.push(twin);
+        
+        let report = config.validate();
+        assert!(!report.is_ok());
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+        assert!(report.errors.iter().all(|e| e.starts_with("measurement 'test': ")));
+        assert!(report.errors.iter().any(|e| e.contains("duplicate measurement name")));
+        assert!(report.errors.iter().any(|e| e.contains("interval_id pattern")));
+        
+        assert_eq!(report.warnings.len(), 2, "{:?}", report.warnings);
+        assert!(report.warnings.iter().any(|w| w.contains("energy_threshold 2")));
+        assert!(report.warnings.iter().any(|w| w.contains("is disabled")));
+    }
+    
+    #[test]
+    fn refresh_must_not_outrun_the_query_window() {
+        let mut config = config();
+        config.matcher_query.window_secs = 10;
+        config.matcher_query.refresh_secs = Some(10);
+        assert!(config.validate().is_ok());
+        
+        config.matcher_query.refresh_secs = Some(11);
+        let errors = config.validate().errors;
+        assert_eq!(errors, vec![
+            "matcher_query.refresh_secs (11) exceeds window_secs (10); traffic between windows would be missed".to_string(),
+        ]);
+    }
+    
+    #[test]
+    fn grace_shorter_than_the_idle_warning_is_flagged() {
+        let mut config = config();
+        let rules = &mut config.measurements[0].signature_rules;
+        rules.idle_warning_secs = 30;
+        rules.stream_grace_secs = Some(30);
+        assert!(config.validate().warnings.is_empty());
+        
+        config.measurements[0].signature_rules.stream_grace_secs = Some(10);
+        let report = config.validate();
+        assert!(report.is_ok());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("stream_grace_secs is below idle_warning_secs"));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::config::{MeasurementConfig, ProtocolType};
+use crate::metrics::SensorMetrics;
+use crate::sample_decode::{encode_samples, resample};
+use crate::signature_detector::SignatureDetector;
+
+/// Where in the recording a signature fired
+#[derive(Debug, Clone)]
+pub struct WavSignatureHit {
+    pub offset_ms: u64,
+    pub hash: u64,
+    pub duration_ms: u32,
+    pub confidence: f32,
+}
+
+/// Feed a WAV recording through a detector in `frame_ms` packets, so users can
+/// check their AudioCriteria/VAD settings against known audio. The recording
+/// is resampled to the measurement's sample_rate and fed as bare audio, even
+/// for RTP measurements (a WAV file has no RTP framing to parse).
+pub fn run_wav(
+    path: &str,
+    measurement: &MeasurementConfig,
+    frame_ms: u32,
+    hash_seed: u64,
+    metrics: &SensorMetrics,
+) -> Result<Vec<WavSignatureHit>, hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    
+    // Normalize whatever bit depth the file uses to -1.0..1.0
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    
+    // Detectors analyse mono audio; average the channels
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    
+    // Packets are built at the rate the detector expects to decode them at
+    let criteria = &measurement.signature_rules.audio_criteria;
+    let target_len = (mono.len() as u64 * criteria.sample_rate as u64 / spec.sample_rate.max(1) as u64) as usize;
+    let mono = resample(&mono, target_len);
+    
+    let mut measurement = measurement.clone();
+    if matches!(measurement.metadata_extraction.protocol, ProtocolType::RTP) {
+        measurement.metadata_extraction.protocol = ProtocolType::Custom { parser_script: String::new() };
+    }
+    
+    let format = &measurement.signature_rules.audio_criteria.sample_format;
+    let samples_per_frame = ((criteria.sample_rate as u64 * frame_ms as u64) / 1000).max(1) as usize;
+    let mut detector = SignatureDetector::new(measurement.clone(), hash_seed, metrics);
+    let mut hits = Vec::new();
+    
+    for (i, frame) in mono.chunks(samples_per_frame).enumerate() {
+        let payload = encode_samples(frame, format);
+        // Recording time stands in for capture time
+        let offset_ns = i as u64 * frame_ms as u64 * 1_000_000;
+        if let Some(sig_event) = detector.detect(&payload, offset_ns) {
+            hits.push(WavSignatureHit {
+                offset_ms: (i as u64 + 1) * frame_ms as u64,
+                hash: sig_event.signature.hash,
+                duration_ms: sig_event.signature.duration_ms,
+                confidence: sig_event.signature.confidence,
+            });
+        }
+    }
+    
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_measurement;
+    use crate::metrics::test_metrics;
+    
+    /// Half a second of silence, then a 440Hz tone until `duration_ms`
+    fn write_wav(name: &str, sample_rate: u32, duration_ms: u32) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        let total = sample_rate * duration_ms / 1000;
+        for i in 0..total {
+            let t = i as f32 / sample_rate as f32;
+            let sample = if t < 0.5 { 0.0 } else { (2.0 * std::f32::consts::PI * 440.0 * t).sin() * 0.5 };
+            writer.write_sample((sample * i16::MAX as f32) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+    
+    #[test]
+    fn recordings_are_resampled_to_the_measurement_rate() {
+        let native = write_wav("wav-8k", 8000, 1500);
+        let oversampled = write_wav("wav-48k", 48000, 1500);
+        let metrics = test_metrics();
+        let native_hits = run_wav(native.to_str().unwrap(), &test_measurement(), 20, 0, &metrics).unwrap();
+        let oversampled_hits = run_wav(oversampled.to_str().unwrap(), &test_measurement(), 20, 0, &metrics).unwrap();
+        std::fs::remove_file(&native).unwrap();
+        std::fs::remove_file(&oversampled).unwrap();
+        
+        // Same audio, same packets once at 8kHz, whatever rate it was recorded at
+        let first = native_hits.first().expect("tone should be detected");
+        let resampled = oversampled_hits.first().expect("tone should be detected");
+        assert_eq!((resampled.offset_ms, resampled.hash), (first.offset_ms, first.hash));
+    }
+    
+    #[test]
+    fn rtp_measurements_read_bare_audio() {
+        let path = write_wav("wav-rtp", 8000, 1500);
+        let metrics = test_metrics();
+        let mut rtp = test_measurement();
+        rtp.metadata_extraction.protocol = ProtocolType::RTP;
+        let rtp_hits = run_wav(path.to_str().unwrap(), &rtp, 20, 0, &metrics).unwrap();
+        let raw_hits = run_wav(path.to_str().unwrap(), &test_measurement(), 20, 0, &metrics).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        
+        assert!(!rtp_hits.is_empty());
+        assert_eq!(
+            rtp_hits.iter().map(|hit| hit.hash).collect::<Vec<_>>(),
+            raw_hits.iter().map(|hit| hit.hash).collect::<Vec<_>>(),
+        );
+    }
+}
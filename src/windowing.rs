@@ -0,0 +1,85 @@
+use crate::config::WindowFunction;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+
+impl WindowFunction {
+    /// Window coefficients for an `n`-point frame
+    pub fn coefficients(&self, n: usize) -> Vec<f32> {
+        if n <= 1 {
+            return vec![1.0; n];
+        }
+        
+        let denom = (n - 1) as f32;
+        (0..n)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / denom;
+                match self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => 0.5 - 0.5 * x.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * x.cos(),
+                    WindowFunction::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Window a frame in place to limit spectral leakage before an FFT
+pub fn apply_window(frame: &mut [f32], window: &WindowFunction) {
+    let coefficients = window.coefficients(frame.len());
+    for (sample, coeff) in frame.iter_mut().zip(coefficients) {
+        *sample *= coeff;
+    }
+}
+
+/// Power per FFT bin (DC .. Nyquist) of a windowed frame.
+/// Bin `k` covers frequency `k * sample_rate / frame.len()`.
+pub fn power_spectrum(frame: &[f32], window: &WindowFunction) -> Vec<f32> {
+    let mut windowed = frame.to_vec();
+    apply_window(&mut windowed, window);
+    
+    let mut buffer: Vec<Complex<f32>> = windowed.iter().map(|&s| Complex::new(s, 0.0)).collect();
+    FftPlanner::new().plan_fft_forward(buffer.len()).process(&mut buffer);
+    
+    buffer[..frame.len() / 2 + 1]
+        .iter()
+        .map(|c| c.norm_sqr())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn tapered_windows_are_symmetric_and_peak_in_the_middle() {
+        for window in [WindowFunction::Hann, WindowFunction::Hamming, WindowFunction::Blackman] {
+            let coefficients = window.coefficients(9);
+            assert!((coefficients[4] - 1.0).abs() < 1e-6, "{:?}", window);
+            for i in 0..9 {
+                assert!((coefficients[i] - coefficients[8 - i]).abs() < 1e-6, "{:?}", window);
+            }
+        }
+        assert_eq!(WindowFunction::Hann.coefficients(9)[0], 0.0);
+        assert!((WindowFunction::Hamming.coefficients(9)[0] - 0.08).abs() < 1e-6);
+        assert_eq!(WindowFunction::Rectangular.coefficients(4), vec![1.0; 4]);
+    }
+    
+    #[test]
+    fn tone_lands_in_its_bin_and_windowing_limits_leakage() {
+        // 1046.875Hz sits halfway between bins at 8kHz / 256, the worst case for leakage
+        let frame: Vec<f32> = (0..256)
+            .map(|i| (2.0 * PI * 1046.875 * i as f32 / 8000.0).sin())
+            .collect();
+        let far_leakage = |window: &WindowFunction| {
+            let spectrum = power_spectrum(&frame, window);
+            assert_eq!(spectrum.len(), 129);
+            let peak = (0..spectrum.len()).max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b])).unwrap();
+            assert!((33..=34).contains(&peak), "peak at bin {}", peak);
+            spectrum[80] / spectrum[peak]
+        };
+        
+        assert!(far_leakage(&WindowFunction::Hann) < far_leakage(&WindowFunction::Rectangular) / 100.0);
+    }
+}